@@ -1,9 +1,26 @@
-use anyhow::Result;
+use crate::finality::WriteMode;
+use crate::forks::OrphanPolicy;
+use crate::recovery::RecoveryPolicy;
+use crate::retry::RetryPolicy;
+use anyhow::{Result, anyhow, bail};
 use std::env;
+use std::time::Duration;
+
+pub const DEFAULT_STREAM: &str = "yellowstone_gRPC_streams";
+pub const DEFAULT_GROUP: &str = "db_processor";
 
 pub struct Config {
     pub redis_url: String,
+    pub stream_name: String,
+    pub group_name: String,
+    /// Unique per replica and stable across its restarts; replicas share the group and
+    /// split the stream.
+    pub consumer_name: String,
     pub scylla_nodes: Vec<String>,
+    pub orphan_policy: OrphanPolicy,
+    pub write_mode: WriteMode,
+    pub recovery: RecoveryPolicy,
+    pub retry: RetryPolicy,
 }
 
 impl Config {
@@ -15,9 +32,92 @@ impl Config {
             .map(|s| s.trim().to_string())
             .collect();
 
+        let defaults = RecoveryPolicy::default();
+        let recovery = RecoveryPolicy {
+            min_idle: match env::var("CLAIM_MIN_IDLE_MS") {
+                Ok(ms) => Duration::from_millis(ms.parse()?),
+                Err(_) => defaults.min_idle,
+            },
+            check_interval: match env::var("PENDING_CHECK_INTERVAL_MS") {
+                Ok(ms) => Duration::from_millis(ms.parse()?),
+                Err(_) => defaults.check_interval,
+            },
+            max_deliveries: match env::var("MAX_DELIVERIES") {
+                Ok(count) => count.parse()?,
+                Err(_) => defaults.max_deliveries,
+            },
+        };
+        if recovery.check_interval.is_zero() || recovery.max_deliveries == 0 {
+            bail!("PENDING_CHECK_INTERVAL_MS and MAX_DELIVERIES must be greater than zero");
+        }
+
+        let defaults = RetryPolicy::default();
+        let retry = RetryPolicy {
+            initial_backoff: match env::var("RETRY_INITIAL_BACKOFF_MS") {
+                Ok(ms) => Duration::from_millis(ms.parse()?),
+                Err(_) => defaults.initial_backoff,
+            },
+            max_backoff: match env::var("RETRY_MAX_BACKOFF_MS") {
+                Ok(ms) => Duration::from_millis(ms.parse()?),
+                Err(_) => defaults.max_backoff,
+            },
+            max_attempts: match env::var("RETRY_MAX_ATTEMPTS") {
+                Ok(count) => count.parse()?,
+                Err(_) => defaults.max_attempts,
+            },
+            breaker_cooldown: match env::var("BREAKER_COOLDOWN_MS") {
+                Ok(ms) => Duration::from_millis(ms.parse()?),
+                Err(_) => defaults.breaker_cooldown,
+            },
+        };
+        if retry.max_attempts == 0 {
+            bail!("RETRY_MAX_ATTEMPTS must be greater than zero");
+        }
+
         Ok(Self {
             redis_url: env::var("REDIS_URL")?,
+            stream_name: env::var("REDIS_STREAM").unwrap_or_else(|_| DEFAULT_STREAM.to_string()),
+            group_name: env::var("CONSUMER_GROUP").unwrap_or_else(|_| DEFAULT_GROUP.to_string()),
+            consumer_name: env::var("CONSUMER_NAME").unwrap_or_else(|_| default_consumer_name()),
             scylla_nodes,
+            orphan_policy: match env::var("ORPHAN_POLICY") {
+                Ok(policy) => policy.parse()?,
+                Err(_) => OrphanPolicy::default(),
+            },
+            write_mode: match env::var("WRITE_MODE") {
+                Ok(mode) => mode.parse()?,
+                Err(_) => WriteMode::default(),
+            },
+            recovery,
+            retry,
         })
     }
+
+    /// Applies `--stream`, `--group` and `--consumer` from the command line over the
+    /// environment and returns the remaining arguments.
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<Vec<String>> {
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let target = match arg.as_str() {
+                "--stream" => &mut self.stream_name,
+                "--group" => &mut self.group_name,
+                "--consumer" => &mut self.consumer_name,
+                _ => {
+                    rest.push(arg);
+                    continue;
+                }
+            };
+            *target = args
+                .next()
+                .ok_or_else(|| anyhow!("{} needs a value", arg))?;
+        }
+        Ok(rest)
+    }
+}
+
+/// The hostname, which stays the same across restarts so a restarted replica replays
+/// its own pending messages. Replicas sharing a host need a `CONSUMER_NAME` each.
+pub fn default_consumer_name() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
 }
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result, anyhow, bail};
+use redis::streams::{StreamId, StreamRangeReply};
+use redis::{Client, Commands};
+use yellowstone_gRPC::codec::{CODEC_FIELD, Codec, PAYLOAD_FIELD};
+use yellowstone_gRPC::schema::Decoded;
+use yellowstone_gRPC::types::IndexEvent;
+
+/// Why the message was dead-lettered.
+pub const REASON_FIELD: &str = "reason";
+/// How often the message was delivered before it was dead-lettered, across replays. Also
+/// set on entries replayed into the main stream.
+pub const ATTEMPTS_FIELD: &str = "attempts";
+/// ID of the message in the main stream.
+pub const ORIGINAL_ID_FIELD: &str = "original_id";
+pub const FAILED_AT_FIELD: &str = "failed_at";
+
+const USAGE: &str = "usage: db_processor dead-letter <list [COUNT] | show ID | replay ID...|--all | purge ID...|--all>";
+
+/// Name of the dead-letter stream of `stream_name`.
+pub fn stream_name(stream_name: &str) -> String {
+    format!("{}:dead_letter", stream_name)
+}
+
+/// An entry of the dead-letter stream.
+pub struct DeadLetter {
+    pub id: String,
+    pub original_id: String,
+    pub reason: String,
+    pub attempts: u32,
+    pub failed_at: String,
+    pub payload: Vec<u8>,
+    pub codec: Option<String>,
+}
+
+impl DeadLetter {
+    fn from_entry(entry: &StreamId) -> Self {
+        Self {
+            id: entry.id.clone(),
+            original_id: entry.get(ORIGINAL_ID_FIELD).unwrap_or_default(),
+            reason: entry.get(REASON_FIELD).unwrap_or_default(),
+            attempts: entry.get(ATTEMPTS_FIELD).unwrap_or_default(),
+            failed_at: entry.get(FAILED_AT_FIELD).unwrap_or_default(),
+            payload: entry.get(PAYLOAD_FIELD).unwrap_or_default(),
+            codec: entry.get(CODEC_FIELD),
+        }
+    }
+
+    fn decode(&self) -> Result<Decoded> {
+        Codec::from_header(self.codec.as_deref())?.decode(&self.payload)
+    }
+}
+
+/// Runs a `db_processor dead-letter` command against the dead-letter stream of
+/// `stream_name`.
+pub fn run(redis_url: &str, stream_name: &str, args: &[String]) -> Result<()> {
+    let mut connection = Client::open(redis_url)?.get_connection()?;
+    let dead_letter_stream = self::stream_name(stream_name);
+    let (command, ids) = args.split_first().ok_or_else(|| anyhow!(USAGE))?;
+
+    match command.as_str() {
+        "list" => {
+            let count = match ids.first() {
+                Some(count) => count.parse().context("COUNT must be a number")?,
+                None => 20,
+            };
+            let reply: StreamRangeReply =
+                connection.xrange_count(&dead_letter_stream, "-", "+", count)?;
+            let length: usize = connection.xlen(&dead_letter_stream)?;
+            println!("{} holds {} messages", dead_letter_stream, length);
+            for letter in reply.ids.iter().map(DeadLetter::from_entry) {
+                let kind = match letter.decode() {
+                    Ok(Decoded::Event(event)) => event_kind(&event).to_string(),
+                    Ok(Decoded::Unknown { kind, .. }) => kind,
+                    Err(_) => "undecodable".to_string(),
+                };
+                println!(
+                    "{}  {} from {}, {} attempts, failed at {}: {}",
+                    letter.id,
+                    kind,
+                    letter.original_id,
+                    letter.attempts,
+                    letter.failed_at,
+                    letter.reason
+                );
+            }
+        }
+        "show" => {
+            let [id] = ids else {
+                bail!(USAGE);
+            };
+            let reply: StreamRangeReply =
+                connection.xrange_count(&dead_letter_stream, id, id, 1)?;
+            let entry = reply
+                .ids
+                .first()
+                .ok_or_else(|| anyhow!("no dead letter {} in {}", id, dead_letter_stream))?;
+            let letter = DeadLetter::from_entry(entry);
+            println!("id: {}", letter.id);
+            println!("original_id: {}", letter.original_id);
+            println!("attempts: {}", letter.attempts);
+            println!("failed_at: {}", letter.failed_at);
+            println!("reason: {}", letter.reason);
+            println!(
+                "codec: {}",
+                letter.codec.as_deref().unwrap_or("none (json)")
+            );
+            match letter.decode() {
+                Ok(Decoded::Event(event)) => print_event(&event),
+                Ok(Decoded::Unknown {
+                    kind,
+                    schema_version,
+                }) => println!(
+                    "{} event of unknown schema version {}",
+                    kind, schema_version
+                ),
+                Err(e) => println!(
+                    "undecodable payload ({}): {}",
+                    e,
+                    String::from_utf8_lossy(&letter.payload)
+                ),
+            }
+        }
+        "replay" => {
+            let letters = select(&mut connection, &dead_letter_stream, ids)?;
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for letter in &letters {
+                let mut fields: Vec<(&str, Vec<u8>)> = vec![
+                    (PAYLOAD_FIELD, letter.payload.clone()),
+                    (ATTEMPTS_FIELD, letter.attempts.to_string().into_bytes()),
+                ];
+                if let Some(codec) = &letter.codec {
+                    fields.push((CODEC_FIELD, codec.clone().into_bytes()));
+                }
+                pipe.xadd(stream_name, "*", &fields).ignore();
+                pipe.xdel(&dead_letter_stream, &[&letter.id]).ignore();
+            }
+            pipe.query::<()>(&mut connection)?;
+            println!("Replayed {} messages into {}", letters.len(), stream_name);
+        }
+        "purge" => {
+            let letters = select(&mut connection, &dead_letter_stream, ids)?;
+            let letter_ids: Vec<&str> = letters.iter().map(|letter| letter.id.as_str()).collect();
+            if !letter_ids.is_empty() {
+                connection.xdel::<_, _, ()>(&dead_letter_stream, &letter_ids)?;
+            }
+            println!(
+                "Purged {} messages from {}",
+                letters.len(),
+                dead_letter_stream
+            );
+        }
+        _ => bail!(USAGE),
+    }
+
+    Ok(())
+}
+
+/// The dead letters named by `ids`, or all of them for `--all`.
+fn select(
+    connection: &mut redis::Connection,
+    dead_letter_stream: &str,
+    ids: &[String],
+) -> Result<Vec<DeadLetter>> {
+    if ids.is_empty() {
+        bail!(USAGE);
+    }
+    if ids.iter().any(|id| id == "--all") {
+        let reply: StreamRangeReply = connection.xrange_all(dead_letter_stream)?;
+        return Ok(reply.ids.iter().map(DeadLetter::from_entry).collect());
+    }
+
+    let mut letters = Vec::with_capacity(ids.len());
+    for id in ids {
+        let reply: StreamRangeReply = connection.xrange_count(dead_letter_stream, id, id, 1)?;
+        let entry = reply
+            .ids
+            .first()
+            .ok_or_else(|| anyhow!("no dead letter {} in {}", id, dead_letter_stream))?;
+        letters.push(DeadLetter::from_entry(entry));
+    }
+    Ok(letters)
+}
+
+fn event_kind(event: &IndexEvent) -> &'static str {
+    match event {
+        IndexEvent::Transaction(_) => "Transaction",
+        IndexEvent::Account(_) => "Account",
+        IndexEvent::Slot(_) => "Slot",
+        IndexEvent::Block(_) => "Block",
+        IndexEvent::TransactionStatus(_) => "TransactionStatus",
+    }
+}
+
+fn print_event(event: &IndexEvent) {
+    match event {
+        IndexEvent::Transaction(transaction) => println!("{}", transaction),
+        IndexEvent::Account(account) => println!("{}", account),
+        IndexEvent::Slot(slot) => println!("{}", slot),
+        IndexEvent::Block(block) => println!("{}", block),
+        IndexEvent::TransactionStatus(status) => println!("{}", status),
+    }
+}
@@ -0,0 +1,296 @@
+use crate::forks::ForkTracker;
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use tracing::info;
+use yellowstone_gRPC::types::{IndexEvent, SlotStatus, SlotUpdate};
+
+/// When transactions and account writes are written to ScyllaDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteMode {
+    /// Write as soon as the event is consumed.
+    #[default]
+    Immediate,
+    /// Hold events until their slot is confirmed.
+    Confirmed,
+    /// Hold events until their slot is finalized.
+    Finalized,
+}
+
+impl FromStr for WriteMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "immediate" => Ok(Self::Immediate),
+            "confirmed" => Ok(Self::Confirmed),
+            "finalized" => Ok(Self::Finalized),
+            other => Err(anyhow!(
+                "unknown write mode '{}' (expected immediate, confirmed or finalized)",
+                other
+            )),
+        }
+    }
+}
+
+/// What the gate decided for a transaction or account event.
+#[allow(clippy::large_enum_variant)]
+pub enum Admission {
+    /// The slot already reached the required commitment; write it now.
+    Write(String, IndexEvent),
+    /// Buffered until its slot is confirmed or finalized.
+    Held,
+    /// The slot was orphaned; acknowledge the message without writing it.
+    Discard(String),
+}
+
+/// Events released by a slot update.
+#[derive(Default)]
+pub struct Released {
+    pub write: Vec<(String, IndexEvent)>,
+    pub discard: Vec<String>,
+}
+
+/// Buffers transaction and account events per slot until the slot reaches the commitment
+/// required by the [`WriteMode`]. Held events are not acknowledged, so after a restart
+/// they are redelivered from the consumer's pending list and buffered again.
+pub struct FinalityGate {
+    mode: WriteMode,
+    reached: BTreeSet<u64>,
+    root: Option<u64>,
+    pending: BTreeMap<u64, Vec<(String, IndexEvent)>>,
+}
+
+impl FinalityGate {
+    pub fn new(mode: WriteMode) -> Self {
+        Self {
+            mode,
+            reached: BTreeSet::new(),
+            root: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn held(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    /// Message IDs of the held events.
+    pub fn held_ids(&self) -> impl Iterator<Item = &str> {
+        self.pending
+            .values()
+            .flatten()
+            .map(|(message_id, _)| message_id.as_str())
+    }
+
+    pub fn admit(
+        &mut self,
+        message_id: String,
+        event: IndexEvent,
+        forks: &ForkTracker,
+    ) -> Admission {
+        let slot = match &event {
+            IndexEvent::Transaction(transaction) => transaction.slot,
+            IndexEvent::Account(account) => account.slot,
+            IndexEvent::TransactionStatus(status) => status.slot,
+            _ => return Admission::Write(message_id, event),
+        };
+
+        if self.mode == WriteMode::Immediate {
+            return Admission::Write(message_id, event);
+        }
+        if forks.is_orphaned(slot) {
+            return Admission::Discard(message_id);
+        }
+        if self.is_ready(slot) {
+            return Admission::Write(message_id, event);
+        }
+
+        self.pending
+            .entry(slot)
+            .or_default()
+            .push((message_id, event));
+        Admission::Held
+    }
+
+    /// Records a slot update (after the fork tracker saw it) and releases the events it
+    /// unblocks or orphans.
+    pub fn on_slot(&mut self, update: &SlotUpdate, forks: &ForkTracker) -> Released {
+        let mut released = Released::default();
+        if self.mode == WriteMode::Immediate {
+            return released;
+        }
+
+        let reached = match update.status {
+            SlotStatus::Confirmed => self.mode == WriteMode::Confirmed,
+            SlotStatus::Finalized => true,
+            SlotStatus::Processed | SlotStatus::Dead => false,
+        };
+        if reached {
+            self.reached.insert(update.slot);
+        }
+        if update.status == SlotStatus::Finalized {
+            self.root = Some(self.root.map_or(update.slot, |root| root.max(update.slot)));
+        }
+
+        let slots: Vec<u64> = self
+            .pending
+            .keys()
+            .copied()
+            .filter(|&slot| forks.is_orphaned(slot) || self.is_ready(slot))
+            .collect();
+        for slot in slots {
+            let Some(events) = self.pending.remove(&slot) else {
+                continue;
+            };
+            if forks.is_orphaned(slot) {
+                info!(
+                    "Discarding {} held events of orphaned slot {}",
+                    events.len(),
+                    slot
+                );
+                released
+                    .discard
+                    .extend(events.into_iter().map(|(message_id, _)| message_id));
+            } else {
+                released.write.extend(events);
+            }
+        }
+
+        if let Some(root) = self.root {
+            self.reached = self.reached.split_off(&root);
+        }
+        released
+    }
+
+    /// A slot is ready once it reached the required commitment, or once it is at or
+    /// below the finalized root without being orphaned (its status update may have been
+    /// consumed before a restart).
+    fn is_ready(&self, slot: u64) -> bool {
+        self.reached.contains(&slot) || self.root.is_some_and(|root| slot <= root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use yellowstone_gRPC::types::TransactionStatus;
+
+    fn status(slot: u64) -> IndexEvent {
+        IndexEvent::TransactionStatus(TransactionStatus {
+            signature: format!("signature-{}", slot),
+            slot,
+            index: 0,
+            is_vote: false,
+            success: true,
+            error: None,
+            timestamp: Utc::now(),
+            ingested_at: Utc::now(),
+        })
+    }
+
+    fn update(slot: u64, parent: Option<u64>, status: SlotStatus) -> SlotUpdate {
+        SlotUpdate {
+            slot,
+            parent,
+            status,
+            dead_error: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Feeds a slot update through the fork tracker and the gate, returning the message
+    /// IDs it released for writing and for discarding.
+    fn on_slot(
+        gate: &mut FinalityGate,
+        forks: &mut ForkTracker,
+        update: SlotUpdate,
+    ) -> (Vec<String>, Vec<String>) {
+        forks.on_slot(&update);
+        let released = gate.on_slot(&update, forks);
+        let write = released
+            .write
+            .into_iter()
+            .map(|(message_id, _)| message_id)
+            .collect();
+        (write, released.discard)
+    }
+
+    #[test]
+    fn writes_immediately_without_holding() {
+        let mut gate = FinalityGate::new(WriteMode::Immediate);
+        let forks = ForkTracker::default();
+        let admission = gate.admit("1-0".to_string(), status(11), &forks);
+        assert!(matches!(admission, Admission::Write(message_id, _) if message_id == "1-0"));
+        assert_eq!(gate.held(), 0);
+    }
+
+    #[test]
+    fn holds_events_until_their_slot_is_confirmed() {
+        let mut gate = FinalityGate::new(WriteMode::Confirmed);
+        let mut forks = ForkTracker::default();
+        assert!(matches!(
+            gate.admit("1-0".to_string(), status(11), &forks),
+            Admission::Held
+        ));
+        assert_eq!(gate.held_ids().collect::<Vec<_>>(), ["1-0"]);
+
+        let processed = update(11, Some(10), SlotStatus::Processed);
+        assert_eq!(on_slot(&mut gate, &mut forks, processed), (vec![], vec![]));
+        let confirmed = update(11, None, SlotStatus::Confirmed);
+        assert_eq!(
+            on_slot(&mut gate, &mut forks, confirmed),
+            (vec!["1-0".to_string()], vec![])
+        );
+        assert_eq!(gate.held(), 0);
+
+        // Later events of a confirmed slot are not held.
+        assert!(matches!(
+            gate.admit("2-0".to_string(), status(11), &forks),
+            Admission::Write(..)
+        ));
+    }
+
+    #[test]
+    fn discards_held_events_of_orphaned_slots() {
+        let mut gate = FinalityGate::new(WriteMode::Finalized);
+        let mut forks = ForkTracker::default();
+        on_slot(
+            &mut gate,
+            &mut forks,
+            update(10, Some(9), SlotStatus::Finalized),
+        );
+        for (slot, parent) in [(11, 10), (12, 10)] {
+            on_slot(
+                &mut gate,
+                &mut forks,
+                update(slot, Some(parent), SlotStatus::Processed),
+            );
+        }
+        gate.admit("1-0".to_string(), status(11), &forks);
+        gate.admit("2-0".to_string(), status(12), &forks);
+
+        // Confirmation is not enough in finalized mode.
+        let confirmed = update(12, None, SlotStatus::Confirmed);
+        assert_eq!(on_slot(&mut gate, &mut forks, confirmed), (vec![], vec![]));
+
+        // Finalizing 12 abandons 11, the fork next to it.
+        let finalized = update(12, None, SlotStatus::Finalized);
+        assert_eq!(
+            on_slot(&mut gate, &mut forks, finalized),
+            (vec!["2-0".to_string()], vec!["1-0".to_string()])
+        );
+        assert_eq!(gate.held(), 0);
+
+        assert!(matches!(
+            gate.admit("3-0".to_string(), status(11), &forks),
+            Admission::Discard(message_id) if message_id == "3-0"
+        ));
+        // Below the root and not orphaned, e.g. its status was consumed before a restart.
+        assert!(matches!(
+            gate.admit("4-0".to_string(), status(10), &forks),
+            Admission::Write(..)
+        ));
+    }
+}
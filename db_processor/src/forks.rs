@@ -0,0 +1,235 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::str::FromStr;
+use tracing::warn;
+use yellowstone_gRPC::types::{SlotStatus, SlotUpdate};
+
+/// How far below the finalized root orphaned slots are remembered, so rows that arrive
+/// late for an orphaned slot are still caught.
+pub const ORPHAN_MEMORY_SLOTS: u64 = 1_000;
+
+/// What happens to transactions and account writes of orphaned slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrphanPolicy {
+    /// Keep the rows and set their `orphaned` column.
+    #[default]
+    Mark,
+    /// Delete the rows.
+    Delete,
+}
+
+impl FromStr for OrphanPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "mark" => Ok(Self::Mark),
+            "delete" => Ok(Self::Delete),
+            other => Err(anyhow!(
+                "unknown orphan policy '{}' (expected mark or delete)",
+                other
+            )),
+        }
+    }
+}
+
+/// Follows slot parentage from slot updates and reports slots that will never be
+/// finalized: dead slots, and slots on forks abandoned by a newer finalized slot.
+///
+/// When a slot is finalized its parent chain back to the previous finalized root is
+/// canonical; every other slot seen in that range was on an abandoned fork. If a missed
+/// update leaves a gap in that chain, a slot below the gap is only orphaned once its own
+/// parents are known to fork off below the previous root.
+#[derive(Default)]
+pub struct ForkTracker {
+    parents: BTreeMap<u64, Option<u64>>,
+    root: Option<u64>,
+    orphaned: BTreeSet<u64>,
+}
+
+impl ForkTracker {
+    pub fn is_orphaned(&self, slot: u64) -> bool {
+        self.orphaned.contains(&slot)
+    }
+
+    /// Records a slot update and returns the slots it orphaned.
+    pub fn on_slot(&mut self, update: &SlotUpdate) -> Vec<u64> {
+        if self.root.is_some_and(|root| update.slot <= root) {
+            return Vec::new();
+        }
+
+        let parent = self.parents.entry(update.slot).or_default();
+        if update.parent.is_some() {
+            *parent = update.parent;
+        }
+
+        match update.status {
+            SlotStatus::Dead => self.orphan(vec![update.slot]),
+            SlotStatus::Finalized => self.finalize(update.slot),
+            SlotStatus::Processed | SlotStatus::Confirmed => Vec::new(),
+        }
+    }
+
+    fn finalize(&mut self, slot: u64) -> Vec<u64> {
+        let Some(previous_root) = self.root.replace(slot) else {
+            // Nothing to compare against until a second root is known.
+            self.prune(slot);
+            return Vec::new();
+        };
+
+        // Walk the canonical chain down from the new root. A slot whose parent is unknown
+        // (a missed update) ends the walk early; below that gap the chain is unknown.
+        let mut canonical = HashSet::new();
+        let mut known_down_to = slot;
+        let mut current = Some(slot);
+        while let Some(ancestor) = current {
+            if ancestor <= previous_root {
+                known_down_to = previous_root;
+                break;
+            }
+            canonical.insert(ancestor);
+            known_down_to = ancestor;
+            current = self.parents.get(&ancestor).copied().flatten();
+        }
+        if known_down_to > previous_root {
+            warn!(
+                "Parent of canonical slot {} is unknown; not orphaning slots below it by default",
+                known_down_to
+            );
+        }
+
+        let abandoned = self
+            .parents
+            .range(previous_root + 1..slot)
+            .map(|(&candidate, _)| candidate)
+            .filter(|&candidate| {
+                !canonical.contains(&candidate)
+                    && (candidate > known_down_to || self.forks_below(candidate, previous_root))
+            })
+            .collect();
+        let orphaned = self.orphan(abandoned);
+        self.prune(slot);
+        orphaned
+    }
+
+    /// Whether the parent chain of `slot` is known to reach below `root` without passing
+    /// through it, i.e. the slot forked off before the root was finalized.
+    fn forks_below(&self, slot: u64, root: u64) -> bool {
+        let mut current = Some(slot);
+        while let Some(ancestor) = current {
+            if ancestor <= root {
+                return ancestor < root;
+            }
+            current = self.parents.get(&ancestor).copied().flatten();
+        }
+        false
+    }
+
+    fn orphan(&mut self, slots: Vec<u64>) -> Vec<u64> {
+        slots
+            .into_iter()
+            .filter(|&slot| self.orphaned.insert(slot))
+            .collect()
+    }
+
+    fn prune(&mut self, root: u64) {
+        self.parents = self.parents.split_off(&(root + 1));
+        self.orphaned = self
+            .orphaned
+            .split_off(&root.saturating_sub(ORPHAN_MEMORY_SLOTS));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn update(slot: u64, parent: Option<u64>, status: SlotStatus) -> SlotUpdate {
+        SlotUpdate {
+            slot,
+            parent,
+            status,
+            dead_error: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn orphans_a_dead_slot_once() {
+        let mut forks = ForkTracker::default();
+        assert!(
+            forks
+                .on_slot(&update(11, Some(10), SlotStatus::Processed))
+                .is_empty()
+        );
+        assert_eq!(forks.on_slot(&update(11, None, SlotStatus::Dead)), [11]);
+        assert!(forks.is_orphaned(11));
+        assert!(
+            forks
+                .on_slot(&update(11, None, SlotStatus::Dead))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn orphans_slots_on_an_abandoned_fork() {
+        let mut forks = ForkTracker::default();
+        assert!(
+            forks
+                .on_slot(&update(10, Some(9), SlotStatus::Finalized))
+                .is_empty()
+        );
+
+        // 10 <- 11 <- 12 <- 14 is canonical; 13 and 15 forked off 11 and 13.
+        for (slot, parent) in [(11, 10), (12, 11), (13, 11), (14, 12), (15, 13)] {
+            forks.on_slot(&update(slot, Some(parent), SlotStatus::Processed));
+        }
+        assert_eq!(
+            forks.on_slot(&update(14, None, SlotStatus::Finalized)),
+            [13]
+        );
+        assert!(forks.is_orphaned(13));
+        assert!(!forks.is_orphaned(12));
+
+        // Slot 15 is beyond the new root; it goes once a root past it is finalized.
+        forks.on_slot(&update(16, Some(14), SlotStatus::Processed));
+        assert_eq!(
+            forks.on_slot(&update(16, None, SlotStatus::Finalized)),
+            [15]
+        );
+
+        // Updates at or below the root change nothing.
+        assert!(
+            forks
+                .on_slot(&update(12, None, SlotStatus::Dead))
+                .is_empty()
+        );
+        assert!(!forks.is_orphaned(12));
+    }
+
+    #[test]
+    fn leaves_slots_below_a_gap_in_the_canonical_chain() {
+        let mut forks = ForkTracker::default();
+        forks.on_slot(&update(10, Some(9), SlotStatus::Finalized));
+
+        // Canonical slot 13 arrived without its parent, so the chain 14 <- 13 stops there.
+        // 11 may be canonical, 12 forked off below the root and 15 forked off 13.
+        for (slot, parent) in [
+            (11, Some(10)),
+            (12, Some(8)),
+            (13, None),
+            (14, Some(13)),
+            (15, Some(13)),
+        ] {
+            forks.on_slot(&update(slot, parent, SlotStatus::Processed));
+        }
+
+        assert_eq!(
+            forks.on_slot(&update(16, Some(14), SlotStatus::Finalized)),
+            [12, 15]
+        );
+        assert!(!forks.is_orphaned(11));
+        assert!(!forks.is_orphaned(13));
+    }
+}
@@ -14,5 +14,8 @@ pub fn logging(message: IndexEvent) {
         IndexEvent::Block(block) => {
             println!("{}", block);
         }
+        IndexEvent::TransactionStatus(status) => {
+            println!("{}", status);
+        }
     }
 }
@@ -1,12 +1,35 @@
-use crate::{config::Config, redis_client::RedisConsumer, scylla_client::ScyllaWriter};
-use anyhow::Result;
-use tracing::{error, info};
+use crate::{
+    config::Config,
+    finality::FinalityGate,
+    forks::ForkTracker,
+    processor::{Batch, ProcessError},
+    redis_client::RedisConsumer,
+    retry::CircuitBreaker,
+    scylla_client::ScyllaWriter,
+    slot_feed::SlotFeed,
+};
+use anyhow::{Result, anyhow};
+use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info, warn};
+
+/// Longest a read blocks, so a shutdown signal is noticed promptly.
+const MAX_READ_BLOCK: Duration = Duration::from_secs(1);
 
 mod config;
+mod dead_letter;
+mod finality;
+mod forks;
 mod processor;
+mod recovery;
 mod redis_client;
+mod retry;
 mod scylla_client;
 mod scylla_types;
+mod slot_feed;
 
 fn setup_logging() {
     tracing_subscriber::fmt()
@@ -19,61 +42,236 @@ async fn main() -> Result<()> {
     setup_logging();
     dotenv::dotenv().ok();
 
-    let config = Config::from_env().unwrap();
+    let mut config = Config::from_env()?;
+
+    let args = config.apply_args(env::args().skip(1))?;
+    if let Some(("dead-letter", args)) = args
+        .split_first()
+        .map(|(command, args)| (command.as_str(), args))
+    {
+        return dead_letter::run(&config.redis_url, &config.stream_name, args);
+    }
 
     let redis_client = RedisConsumer::new(
         &config.redis_url,
-        "yellowstone_gRPC_streams",
-        "db_processor",
-        "db_processor_consumer_1",
+        &config.stream_name,
+        &config.group_name,
+        &config.consumer_name,
+        config.recovery.max_deliveries,
     )?;
 
-    redis_client.create_consumer_group()?;
+    retry::redis(&config.retry, "creating the consumer group", || {
+        redis_client.create_consumer_group()
+    })
+    .await?;
 
-    info!("Consumer group created successfully");
+    info!(
+        "Consuming {} as {} in group {}",
+        config.stream_name, config.consumer_name, config.group_name
+    );
 
     // Initialize ScyllaDB client
     let scylla_nodes: Vec<&str> = config.scylla_nodes.iter().map(|s| s.as_str()).collect();
-    let mut writer = ScyllaWriter::new(
-        scylla_nodes,
-        "solana_indexer",
-        "accounts",
-        "transactions",
-        1000,
-    )
+    let mut writer = retry::scylla(&config.retry, "connecting", async || {
+        ScyllaWriter::new(
+            scylla_nodes.clone(),
+            "solana_indexer",
+            "accounts",
+            "account_writes",
+            "account_slots",
+            "transactions",
+            "slots",
+            "token_balance_changes",
+            "blocks",
+            "transaction_statuses",
+            1000,
+        )
+        .await
+    })
     .await
-    .unwrap_or_else(|e| {
-        error!("Error creating ScyllaDB writer: {}", e);
-        std::process::exit(1);
-    });
+    .map_err(|e| anyhow!("Error creating ScyllaDB writer: {}", e))?;
 
     // Keyspace and table creation
-    writer.create_keyspace().await.unwrap_or_else(|e| {
-        error!("Error creating keyspace: {}", e);
-        std::process::exit(1);
-    });
-
-    writer.create_accounts_table().await.unwrap_or_else(|e| {
-        error!("Error creating accounts table: {}", e);
-        std::process::exit(1);
-    });
-    writer
-        .create_transactions_table()
-        .await
-        .unwrap_or_else(|e| {
-            error!("Error creating transactions table: {}", e);
-            std::process::exit(1);
-        });
+    retry::scylla(&config.retry, "creating the schema", async || {
+        writer.create_schema().await
+    })
+    .await
+    .map_err(|e| anyhow!("Error creating the keyspace and tables: {}", e))?;
+
+    // Every replica reads every slot update, whichever replica consumed it.
+    let mut slot_feed = SlotFeed::new(
+        &config.redis_url,
+        &slot_feed::stream_name(&config.stream_name),
+    )?;
+    let mut forks = ForkTracker::default();
+    let mut gate = FinalityGate::new(config.write_mode);
+    info!("Write mode: {:?}", config.write_mode);
+    info!("Recovery: {:?}", config.recovery);
+    info!("Retry: {:?}", config.retry);
+
+    let mut breaker = CircuitBreaker::new(config.retry.breaker_cooldown);
+
+    // Own pending messages first, then whatever other consumers left idle.
+    let replayed = recovery::replay_own_pending(
+        &redis_client,
+        &mut writer,
+        &mut slot_feed,
+        &mut forks,
+        &mut gate,
+        config.orphan_policy,
+        &config.retry,
+    )
+    .await;
+    // The rest of a batch ScyllaDB could not take; committed before anything else is read.
+    let mut stalled = settle(replayed, &mut breaker)?;
+    let mut last_recovery: Option<Instant> = None;
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    tokio::spawn(watch_shutdown(shutdown.clone()));
+
+    while !shutdown.load(Ordering::Relaxed) {
+        if breaker.is_open() {
+            let wait = breaker.until_probe();
+            if !wait.is_zero() {
+                tokio::time::sleep(wait.min(MAX_READ_BLOCK)).await;
+                continue;
+            }
+            // Nothing is processed meanwhile, so keep other consumers from claiming the
+            // messages held here.
+            let pending_ids: Vec<&str> = gate
+                .held_ids()
+                .chain(stalled.iter().flat_map(Batch::message_ids))
+                .collect();
+            if let Err(e) = redis_client.touch(&pending_ids) {
+                warn!("Error touching pending messages: {}", e);
+            }
+            match writer.check_health().await {
+                Ok(()) => {
+                    info!("ScyllaDB is available again, resuming");
+                    breaker.close();
+                }
+                Err(e) => {
+                    warn!("ScyllaDB is still unavailable: {}", e);
+                    breaker.trip();
+                    continue;
+                }
+            }
+        }
 
-    loop {
-        match redis_client.consume_message(5, 0) {
+        if let Some(batch) = stalled.take() {
+            let committed = processor::commit(
+                batch,
+                &mut writer,
+                &redis_client,
+                config.orphan_policy,
+                &config.retry,
+            )
+            .await;
+            stalled = settle(committed, &mut breaker)?;
+            continue;
+        }
+
+        if last_recovery.is_none_or(|at| at.elapsed() >= config.recovery.check_interval) {
+            let recovered = recovery::claim_idle(
+                &redis_client,
+                &mut writer,
+                &mut slot_feed,
+                &mut forks,
+                &mut gate,
+                config.orphan_policy,
+                &config.recovery,
+                &config.retry,
+            )
+            .await;
+            stalled = settle(recovered, &mut breaker)?;
+            last_recovery = Some(Instant::now());
+            continue;
+        }
+
+        // Blocks at most until the next recovery is due.
+        let block_ms = config
+            .recovery
+            .check_interval
+            .min(MAX_READ_BLOCK)
+            .as_millis() as usize;
+        let consumed = retry::redis(&config.retry, "reading the stream", || {
+            redis_client.consume_message(5, block_ms)
+        })
+        .await;
+        match consumed {
             Ok(messages) => {
                 info!("Consumed {} messages", messages.len());
-                processor::process(messages, &mut writer, &redis_client).await?;
+                let processed = processor::process(
+                    messages,
+                    &mut writer,
+                    &redis_client,
+                    &mut slot_feed,
+                    &mut forks,
+                    &mut gate,
+                    config.orphan_policy,
+                    &config.retry,
+                )
+                .await;
+                stalled = settle(processed, &mut breaker)?;
             }
             Err(e) => {
                 error!("Error consuming message: {}", e);
             }
         }
     }
+
+    info!("Shutting down");
+    match redis_client.deregister() {
+        Ok(true) => info!(
+            "Removed consumer {} from group {}",
+            config.consumer_name, config.group_name
+        ),
+        Ok(false) => warn!(
+            "Consumer {} still has pending messages ({} held for commitment); leaving it in group {} so they can be claimed",
+            config.consumer_name,
+            gate.held(),
+            config.group_name
+        ),
+        Err(e) => error!("Error removing consumer {}: {}", config.consumer_name, e),
+    }
+    Ok(())
+}
+
+/// Sorts out a processing result: a batch ScyllaDB could not take is kept and opens the
+/// breaker, Redis failures are logged since their messages stay pending and are claimed
+/// again later, and anything else stops the processor.
+fn settle(result: Result<(), ProcessError>, breaker: &mut CircuitBreaker) -> Result<Option<Batch>> {
+    match result {
+        Ok(()) => Ok(None),
+        Err(ProcessError::ScyllaUnavailable { batch, error }) => {
+            breaker.trip();
+            error!(
+                "ScyllaDB is unavailable, pausing consumption for {:?}: {}",
+                breaker.until_probe(),
+                error
+            );
+            Ok(Some(batch))
+        }
+        Err(ProcessError::Redis(e)) => {
+            error!("Redis error, the messages stay pending: {}", e);
+            Ok(None)
+        }
+        Err(ProcessError::Fatal(e)) => Err(e),
+    }
+}
+
+/// Sets `shutdown` on SIGINT or SIGTERM; the main loop stops after the batch in hand.
+async fn watch_shutdown(shutdown: Arc<AtomicBool>) {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!("Error installing the SIGTERM handler: {}", e);
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+    shutdown.store(true, Ordering::Relaxed);
 }
@@ -1,66 +1,330 @@
-use tracing::{error, info};
-use yellowstone_gRPC::types::{IndexEvent, SolanaAccount, SolanaTransaction};
+use tracing::{info, warn};
+use yellowstone_gRPC::types::{
+    IndexEvent, SlotUpdate, SolanaAccount, SolanaBlock, SolanaTransaction, TransactionStatus,
+};
 
-use crate::{redis_client::RedisConsumer, scylla_client::ScyllaWriter};
-use anyhow::Result;
+use crate::{
+    finality::{Admission, FinalityGate},
+    forks::{ForkTracker, OrphanPolicy},
+    redis_client::RedisConsumer,
+    retry::{self, RetryPolicy},
+    scylla_client::ScyllaWriter,
+    slot_feed::SlotFeed,
+};
+use anyhow::anyhow;
+use redis::RedisError;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
 
+/// Events of a batch that are still to be written, the slots they orphan and the
+/// messages to acknowledge once both are done.
+#[derive(Debug, Default)]
+pub struct Batch {
+    ready: Vec<(String, IndexEvent)>,
+    orphaned_slots: BTreeSet<u64>,
+    message_ids: Vec<String>,
+}
+
+impl Batch {
+    /// IDs of every message of the batch, written or not.
+    pub fn message_ids(&self) -> impl Iterator<Item = &str> {
+        self.ready
+            .iter()
+            .map(|(message_id, _)| message_id)
+            .chain(&self.message_ids)
+            .map(String::as_str)
+    }
+}
+
+/// Why a batch could not be processed.
+#[derive(Debug)]
+pub enum ProcessError {
+    /// ScyllaDB still failed with transient errors after the retries. The unfinished
+    /// batch is handed back so it can be committed once ScyllaDB recovers.
+    ScyllaUnavailable { batch: Batch, error: String },
+    /// Redis still failed after the retries. Unacknowledged messages stay pending and are
+    /// claimed again later.
+    Redis(RedisError),
+    /// A failure that retrying does not fix.
+    Fatal(anyhow::Error),
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ScyllaUnavailable { batch, error } => write!(
+                f,
+                "ScyllaDB is unavailable with {} events and {} orphaned slots outstanding: {}",
+                batch.ready.len(),
+                batch.orphaned_slots.len(),
+                error
+            ),
+            Self::Redis(e) => write!(f, "Redis error: {}", e),
+            Self::Fatal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ProcessError {}
+
+impl From<RedisError> for ProcessError {
+    fn from(error: RedisError) -> Self {
+        Self::Redis(error)
+    }
+}
+
+/// Shares the slot updates among `messages` with the other replicas, runs everything
+/// through the fork tracker and the finality gate and commits what is ready. Called with
+/// no messages, it still catches up on the slot feed.
+#[allow(clippy::too_many_arguments)]
 pub async fn process(
     messages: Vec<(String, IndexEvent)>,
     scylla_writer: &mut ScyllaWriter,
     redis_client: &RedisConsumer,
-) -> Result<()> {
-    let mut accounts = Vec::<SolanaAccount>::new();
-    let mut transactions = Vec::<SolanaTransaction>::new();
+    slot_feed: &mut SlotFeed,
+    forks: &mut ForkTracker,
+    gate: &mut FinalityGate,
+    orphan_policy: OrphanPolicy,
+    retry_policy: &RetryPolicy,
+) -> Result<(), ProcessError> {
+    let shared: Vec<&SlotUpdate> = messages
+        .iter()
+        .filter_map(|(_, event)| match event {
+            IndexEvent::Slot(slot) => Some(slot),
+            _ => None,
+        })
+        .collect();
+    retry::redis(retry_policy, "sharing slot updates", || {
+        slot_feed.share(&shared)
+    })
+    .await?;
+    let slot_updates =
+        retry::redis(retry_policy, "reading the slot feed", || slot_feed.read()).await?;
+
+    let batch = admit(messages, slot_updates, forks, gate);
+    commit(
+        batch,
+        scylla_writer,
+        redis_client,
+        orphan_policy,
+        retry_policy,
+    )
+    .await
+}
+
+/// Runs the slot updates of every replica through the fork tracker and the finality gate,
+/// then admits the messages, and collects what is ready to be written. Slot updates among
+/// the messages reached the tracker through the slot feed and are only written here.
+fn admit(
+    messages: Vec<(String, IndexEvent)>,
+    slot_updates: Vec<SlotUpdate>,
+    forks: &mut ForkTracker,
+    gate: &mut FinalityGate,
+) -> Batch {
+    // Slot updates are written after the gate saw them, alongside the released events.
+    let mut slots = Vec::<(String, IndexEvent)>::new();
+    // Slots orphaned by this batch, plus orphaned slots that rows arrived for late.
+    let mut orphaned_slots = BTreeSet::<u64>::new();
+    // Events held by the gate stay unacknowledged until they are written or discarded.
     let mut message_ids = Vec::<String>::new();
+    let mut ready = Vec::<(String, IndexEvent)>::new();
+
+    for slot in &slot_updates {
+        orphaned_slots.extend(forks.on_slot(slot));
+        let released = gate.on_slot(slot, forks);
+        ready.extend(released.write);
+        message_ids.extend(released.discard);
+    }
 
     for (message_id, event) in messages {
         info!("Message ID: {}", message_id);
-        message_ids.push(message_id);
         match event {
+            IndexEvent::Slot(slot) => {
+                info!("{}", slot);
+                slots.push((message_id, IndexEvent::Slot(slot)));
+            }
+            event => match gate.admit(message_id, event, forks) {
+                Admission::Write(message_id, event) => ready.push((message_id, event)),
+                Admission::Held => {}
+                Admission::Discard(message_id) => message_ids.push(message_id),
+            },
+        }
+    }
+    if gate.held() > 0 {
+        info!(
+            "Holding {} events until their slots reach commitment",
+            gate.held()
+        );
+    }
+
+    for (_, event) in &ready {
+        let slot = match event {
             IndexEvent::Transaction(transaction) => {
-                println!("{}", transaction);
-                transactions.push(transaction);
+                info!("{}", transaction);
+                transaction.slot
             }
             IndexEvent::Account(account) => {
-                println!("{}", account);
-                accounts.push(account);
+                info!("{}", account);
+                account.slot
             }
             IndexEvent::Slot(slot) => {
-                println!("{}", slot);
+                info!("{}", slot);
+                continue;
             }
             IndexEvent::Block(block) => {
-                println!("{}", block);
+                info!("{}", block);
+                block.slot
+            }
+            IndexEvent::TransactionStatus(status) => {
+                info!("{}", status);
+                status.slot
             }
+        };
+        if forks.is_orphaned(slot) {
+            orphaned_slots.insert(slot);
         }
     }
+    ready.extend(slots);
 
-    scylla_writer
-        .add_accounts(accounts)
-        .await
-        .unwrap_or_else(|e| {
-            error!("Error adding accounts to ScyllaDB: {}", e);
-            std::process::exit(1);
-        });
-
-    scylla_writer
-        .add_transactions(transactions)
-        .await
-        .unwrap_or_else(|e| {
-            error!("Error adding transactions to ScyllaDB: {}", e);
-            std::process::exit(1);
-        });
-
-    scylla_writer.flush_all_batches().await.unwrap_or_else(|e| {
-        error!("Error flushing all batches: {}", e);
-        std::process::exit(1);
-    });
-    info!("Flushed all batches successfully");
-    redis_client
-        .acknowledge(message_ids.as_slice())
-        .unwrap_or_else(|e| {
-            error!("Error acknowledging messages: {}", e);
-            std::process::exit(1);
-        });
+    Batch {
+        ready,
+        orphaned_slots,
+        message_ids,
+    }
+}
+
+/// Writes the batch, orphans its slots and acknowledges its messages, retrying transient
+/// failures. Events ScyllaDB rejects are dead-lettered. If ScyllaDB stays unavailable,
+/// the rest of the batch comes back in [`ProcessError::ScyllaUnavailable`].
+pub async fn commit(
+    mut batch: Batch,
+    scylla_writer: &mut ScyllaWriter,
+    redis_client: &RedisConsumer,
+    orphan_policy: OrphanPolicy,
+    retry_policy: &RetryPolicy,
+) -> Result<(), ProcessError> {
+    if !batch.ready.is_empty() {
+        let events: Vec<IndexEvent> = batch.ready.iter().map(|(_, event)| event.clone()).collect();
+        let written = retry::scylla(retry_policy, "writing a batch", async || {
+            write_events(scylla_writer, events.clone()).await
+        })
+        .await;
+        match written {
+            Ok(()) => {
+                info!("Flushed all batches successfully");
+                let ready = std::mem::take(&mut batch.ready);
+                batch
+                    .message_ids
+                    .extend(ready.into_iter().map(|(message_id, _)| message_id));
+            }
+            Err(e) if retry::is_transient_scylla(e.as_ref()) => return Err(unavailable(batch, e)),
+            Err(e) => {
+                // Write one event at a time so only the events that fail are dead-lettered.
+                warn!(
+                    "Error writing {} events to ScyllaDB, retrying one by one: {}",
+                    batch.ready.len(),
+                    e
+                );
+                let mut ready = std::mem::take(&mut batch.ready).into_iter();
+                while let Some((message_id, event)) = ready.next() {
+                    let written = retry::scylla(retry_policy, "writing an event", async || {
+                        write_events(scylla_writer, vec![event.clone()]).await
+                    })
+                    .await;
+                    match written {
+                        Ok(()) => batch.message_ids.push(message_id),
+                        Err(e) if retry::is_transient_scylla(e.as_ref()) => {
+                            batch.ready =
+                                std::iter::once((message_id, event)).chain(ready).collect();
+                            return Err(unavailable(batch, e));
+                        }
+                        Err(e) => {
+                            let reason = format!("Error writing to ScyllaDB: {}", e);
+                            retry::redis(retry_policy, "dead-lettering a message", || {
+                                redis_client.dead_letter(&message_id, &reason, &event)
+                            })
+                            .await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs after the flush so rows written in this batch are covered too.
+    while let Some(&slot) = batch.orphaned_slots.first() {
+        let orphaned = retry::scylla(retry_policy, "orphaning a slot", async || {
+            scylla_writer.orphan_slot(slot, orphan_policy).await
+        })
+        .await;
+        match orphaned {
+            Ok(()) => {
+                batch.orphaned_slots.remove(&slot);
+            }
+            Err(e) if retry::is_transient_scylla(e.as_ref()) => return Err(unavailable(batch, e)),
+            Err(e) => {
+                return Err(ProcessError::Fatal(anyhow!(
+                    "Error orphaning slot {}: {}",
+                    slot,
+                    e
+                )));
+            }
+        }
+    }
+
+    if batch.message_ids.is_empty() {
+        return Ok(());
+    }
+    retry::redis(retry_policy, "acknowledging messages", || {
+        redis_client.acknowledge(&batch.message_ids)
+    })
+    .await?;
     info!("Acknowledged messages successfully");
     Ok(())
 }
+
+fn unavailable(batch: Batch, error: Box<dyn Error>) -> ProcessError {
+    ProcessError::ScyllaUnavailable {
+        batch,
+        error: error.to_string(),
+    }
+}
+
+/// Writes `events` and flushes every batch, so a failure belongs to these events. Rows
+/// a failed flush left behind are discarded.
+async fn write_events(
+    scylla_writer: &mut ScyllaWriter,
+    events: Vec<IndexEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let mut accounts = Vec::<SolanaAccount>::new();
+    let mut transactions = Vec::<SolanaTransaction>::new();
+    let mut slots = Vec::<SlotUpdate>::new();
+    let mut blocks = Vec::<SolanaBlock>::new();
+    let mut transaction_statuses = Vec::<TransactionStatus>::new();
+    for event in events {
+        match event {
+            IndexEvent::Transaction(transaction) => transactions.push(transaction),
+            IndexEvent::Account(account) => accounts.push(account),
+            IndexEvent::Slot(slot) => slots.push(slot),
+            IndexEvent::Block(block) => blocks.push(block),
+            IndexEvent::TransactionStatus(status) => transaction_statuses.push(status),
+        }
+    }
+
+    let written = async {
+        scylla_writer.add_accounts(accounts).await?;
+        scylla_writer.add_transactions(transactions).await?;
+        scylla_writer.add_slots(slots).await?;
+        scylla_writer
+            .add_transaction_statuses(transaction_statuses)
+            .await?;
+        scylla_writer.add_blocks(blocks).await?;
+        scylla_writer.flush_all_batches().await
+    }
+    .await;
+    if written.is_err() {
+        scylla_writer.discard_batches();
+    }
+    written
+}
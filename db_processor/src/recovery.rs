@@ -0,0 +1,129 @@
+use crate::{
+    finality::FinalityGate,
+    forks::{ForkTracker, OrphanPolicy},
+    processor::{self, ProcessError},
+    redis_client::RedisConsumer,
+    retry::{self, RetryPolicy},
+    scylla_client::ScyllaWriter,
+    slot_feed::SlotFeed,
+};
+use std::time::Duration;
+use tracing::info;
+
+/// Messages read per pending-list or claim round trip.
+const RECOVERY_BATCH: usize = 100;
+
+/// How messages stuck in the consumer group's pending list are recovered. Every
+/// `check_interval`, messages idle for `min_idle` are claimed from any consumer of the
+/// group; a message delivered more than `max_deliveries` times is dead-lettered instead
+/// of processed again.
+#[derive(Debug, Clone, Copy)]
+pub struct RecoveryPolicy {
+    pub min_idle: Duration,
+    pub check_interval: Duration,
+    pub max_deliveries: usize,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            min_idle: Duration::from_secs(60),
+            check_interval: Duration::from_secs(30),
+            max_deliveries: 5,
+        }
+    }
+}
+
+/// Processes what this consumer read but never acknowledged before a restart, which
+/// includes events that were held waiting for their slot's commitment.
+pub async fn replay_own_pending(
+    redis_client: &RedisConsumer,
+    writer: &mut ScyllaWriter,
+    slot_feed: &mut SlotFeed,
+    forks: &mut ForkTracker,
+    gate: &mut FinalityGate,
+    orphan_policy: OrphanPolicy,
+    retry_policy: &RetryPolicy,
+) -> Result<(), ProcessError> {
+    let mut pending_cursor = "0".to_string();
+    loop {
+        let (last_id, messages) = retry::redis(retry_policy, "reading pending messages", || {
+            redis_client.consume_pending(&pending_cursor, RECOVERY_BATCH)
+        })
+        .await?;
+        let Some(last_id) = last_id else {
+            break;
+        };
+        pending_cursor = last_id;
+        info!("Replaying {} pending messages", messages.len());
+        processor::process(
+            messages,
+            writer,
+            redis_client,
+            slot_feed,
+            forks,
+            gate,
+            orphan_policy,
+            retry_policy,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Claims and processes messages idle in the pending list of any consumer of the group,
+/// e.g. one that crashed, then removes consumers left with nothing pending. Events held
+/// by the gate are touched first so they do not look idle themselves.
+#[allow(clippy::too_many_arguments)]
+pub async fn claim_idle(
+    redis_client: &RedisConsumer,
+    writer: &mut ScyllaWriter,
+    slot_feed: &mut SlotFeed,
+    forks: &mut ForkTracker,
+    gate: &mut FinalityGate,
+    orphan_policy: OrphanPolicy,
+    policy: &RecoveryPolicy,
+    retry_policy: &RetryPolicy,
+) -> Result<(), ProcessError> {
+    let held_ids: Vec<&str> = gate.held_ids().collect();
+    retry::redis(retry_policy, "touching held messages", || {
+        redis_client.touch(&held_ids)
+    })
+    .await?;
+
+    // XAUTOCLAIM returns 0-0 as the next start once the whole pending list was scanned.
+    let mut start = "0-0".to_string();
+    loop {
+        let (next_start, messages) = retry::redis(retry_policy, "claiming idle messages", || {
+            redis_client.claim_idle(&start, policy.min_idle, RECOVERY_BATCH)
+        })
+        .await?;
+        if !messages.is_empty() {
+            info!("Processing {} claimed messages", messages.len());
+            processor::process(
+                messages,
+                writer,
+                redis_client,
+                slot_feed,
+                forks,
+                gate,
+                orphan_policy,
+                retry_policy,
+            )
+            .await?;
+        }
+        if next_start == "0-0" {
+            break;
+        }
+        start = next_start;
+    }
+
+    let removed = retry::redis(retry_policy, "removing idle consumers", || {
+        redis_client.remove_idle_consumers(policy.min_idle)
+    })
+    .await?;
+    if !removed.is_empty() {
+        info!("Removed idle consumers {:?} from the group", removed);
+    }
+    Ok(())
+}
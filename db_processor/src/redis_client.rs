@@ -1,15 +1,31 @@
+use crate::dead_letter::{self, ATTEMPTS_FIELD, FAILED_AT_FIELD, ORIGINAL_ID_FIELD, REASON_FIELD};
+use chrono::Utc;
 use redis::{
-    Client, Commands, RedisResult,
-    streams::{StreamReadOptions, StreamReadReply},
+    Client, Commands, Connection, RedisResult,
+    streams::{
+        StreamAutoClaimOptions, StreamAutoClaimReply, StreamClaimOptions, StreamId,
+        StreamInfoConsumersReply, StreamPendingCountReply, StreamRangeReply, StreamReadOptions,
+        StreamReadReply,
+    },
 };
-use tracing::info;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{info, warn};
+use yellowstone_gRPC::codec::{CODEC_FIELD, Codec, PAYLOAD_FIELD};
+use yellowstone_gRPC::schema::Decoded;
 use yellowstone_gRPC::types::IndexEvent;
 
+/// Decoded stream messages with their IDs, in stream order.
+pub type Messages = Vec<(String, IndexEvent)>;
+
 pub struct RedisConsumer {
     client: Client,
     stream_name: String,
+    dead_letter_stream: String,
     group_name: String,
     consumer_name: String,
+    /// Deliveries after which a message is dead-lettered instead of processed again.
+    max_deliveries: usize,
 }
 
 impl RedisConsumer {
@@ -18,13 +34,16 @@ impl RedisConsumer {
         stream_name: &str,
         group_name: &str,
         consumer_name: &str,
+        max_deliveries: usize,
     ) -> RedisResult<Self> {
         let client = Client::open(redis_url)?;
         Ok(Self {
-            client: client,
+            client,
             stream_name: stream_name.to_string(),
+            dead_letter_stream: dead_letter::stream_name(stream_name),
             group_name: group_name.to_string(),
             consumer_name: consumer_name.to_string(),
+            max_deliveries,
         })
     }
 
@@ -49,6 +68,49 @@ impl RedisConsumer {
         Ok(())
     }
 
+    /// Removes this consumer from the group on shutdown, unless it still has pending
+    /// messages: deleting the consumer would drop them from the pending list, so they are
+    /// left for another processor to claim. Returns whether the consumer was removed.
+    pub fn deregister(&self) -> RedisResult<bool> {
+        let mut conn = self.client.get_connection()?;
+        let pending: StreamPendingCountReply = conn.xpending_consumer_count(
+            &self.stream_name,
+            &self.group_name,
+            "-",
+            "+",
+            1,
+            &self.consumer_name,
+        )?;
+        if !pending.ids.is_empty() {
+            return Ok(false);
+        }
+        let _: i64 =
+            conn.xgroup_delconsumer(&self.stream_name, &self.group_name, &self.consumer_name)?;
+        Ok(true)
+    }
+
+    /// Removes other consumers of the group that have no pending messages and have not
+    /// read for `min_idle`, e.g. replicas that died and whose messages were claimed since.
+    /// A replica that is only quiet joins again on its next read. Returns their names.
+    pub fn remove_idle_consumers(&self, min_idle: Duration) -> RedisResult<Vec<String>> {
+        let mut conn = self.client.get_connection()?;
+        let reply: StreamInfoConsumersReply =
+            conn.xinfo_consumers(&self.stream_name, &self.group_name)?;
+        let mut removed = Vec::new();
+        for consumer in reply.consumers {
+            if consumer.name == self.consumer_name
+                || consumer.pending > 0
+                || (consumer.idle as u128) < min_idle.as_millis()
+            {
+                continue;
+            }
+            let _: i64 =
+                conn.xgroup_delconsumer(&self.stream_name, &self.group_name, &consumer.name)?;
+            removed.push(consumer.name);
+        }
+        Ok(removed)
+    }
+
     pub fn acknowledge(&self, message_ids: &[String]) -> RedisResult<i64> {
         let mut conn = self.client.get_connection()?;
 
@@ -59,45 +121,519 @@ impl RedisConsumer {
         Ok(ack_count)
     }
 
-    pub fn consume_message(
+    /// Moves a message that could not be written to the dead-letter stream and
+    /// acknowledges it. The original payload is taken from the stream; if it was trimmed
+    /// already, `event` is stored re-encoded as JSON instead.
+    pub fn dead_letter(
         &self,
-        count: usize,
-        block_ms: usize,
-    ) -> RedisResult<Vec<(String, IndexEvent)>> {
+        message_id: &str,
+        reason: &str,
+        event: &IndexEvent,
+    ) -> RedisResult<()> {
         let mut conn = self.client.get_connection()?;
+        let entry: StreamRangeReply =
+            conn.xrange_count(&self.stream_name, message_id, message_id, 1)?;
+        let (payload, codec, attempts) = match entry.ids.first() {
+            Some(entry) => (
+                entry.get(PAYLOAD_FIELD).unwrap_or_default(),
+                entry.get(CODEC_FIELD),
+                entry.get(ATTEMPTS_FIELD).unwrap_or(0),
+            ),
+            None => {
+                let codec = Codec::default();
+                let payload = codec.encode(event).map_err(|e| {
+                    redis::RedisError::from((
+                        redis::ErrorKind::TypeError,
+                        "Failed to encode dead letter",
+                        e.to_string(),
+                    ))
+                })?;
+                (payload, Some(codec.header()), 0)
+            }
+        };
+        self.move_to_dead_letter(&mut conn, message_id, payload, codec, attempts, reason)
+    }
+
+    /// Adds the message to the dead-letter stream and acknowledges it in one transaction.
+    /// `previous_attempts` counts deliveries before the message was last replayed.
+    fn move_to_dead_letter(
+        &self,
+        conn: &mut Connection,
+        message_id: &str,
+        payload: Vec<u8>,
+        codec: Option<String>,
+        previous_attempts: u32,
+        reason: &str,
+    ) -> RedisResult<()> {
+        let pending: StreamPendingCountReply = conn.xpending_count(
+            &self.stream_name,
+            &self.group_name,
+            message_id,
+            message_id,
+            1,
+        )?;
+        let delivered = pending
+            .ids
+            .first()
+            .map_or(1, |pending| pending.times_delivered as u32);
+        let attempts = previous_attempts + delivered;
 
-        let result: StreamReadReply = conn.xread_options(
-            &[&self.stream_name],
-            &[">"],
-            &StreamReadOptions::default()
+        let mut fields: Vec<(&str, Vec<u8>)> = vec![
+            (PAYLOAD_FIELD, payload),
+            (REASON_FIELD, reason.as_bytes().to_vec()),
+            (ATTEMPTS_FIELD, attempts.to_string().into_bytes()),
+            (ORIGINAL_ID_FIELD, message_id.as_bytes().to_vec()),
+            (FAILED_AT_FIELD, Utc::now().to_rfc3339().into_bytes()),
+        ];
+        if let Some(codec) = codec {
+            fields.push((CODEC_FIELD, codec.into_bytes()));
+        }
+        redis::pipe()
+            .atomic()
+            .xadd(&self.dead_letter_stream, "*", &fields)
+            .ignore()
+            .xack(&self.stream_name, &self.group_name, &[message_id])
+            .ignore()
+            .query::<()>(conn)?;
+
+        warn!(
+            "Moved message {} to {} after {} attempts: {}",
+            message_id, self.dead_letter_stream, attempts, reason
+        );
+        Ok(())
+    }
+
+    pub fn consume_message(&self, count: usize, block_ms: usize) -> RedisResult<Messages> {
+        let (_, messages) = self.read_group(
+            ">",
+            StreamReadOptions::default()
                 .group(&self.group_name, &self.consumer_name)
                 .count(count)
                 .block(block_ms),
         )?;
+        Ok(messages)
+    }
+
+    /// Reads messages already delivered to this consumer but never acknowledged, starting
+    /// after `after_id` (`"0"` for the beginning of the pending list). Also returns the ID
+    /// of the last entry read, which is `None` once the pending list is exhausted;
+    /// dead-lettered entries are read but not returned.
+    pub fn consume_pending(
+        &self,
+        after_id: &str,
+        count: usize,
+    ) -> RedisResult<(Option<String>, Messages)> {
+        self.read_group(
+            after_id,
+            StreamReadOptions::default()
+                .group(&self.group_name, &self.consumer_name)
+                .count(count),
+        )
+    }
+
+    /// Claims messages idle for at least `min_idle` in the pending list of any consumer
+    /// of the group, scanning from `start`. Also returns where the next scan starts,
+    /// which is `0-0` once the whole pending list was scanned.
+    pub fn claim_idle(
+        &self,
+        start: &str,
+        min_idle: Duration,
+        count: usize,
+    ) -> RedisResult<(String, Messages)> {
+        let mut conn = self.client.get_connection()?;
+        let reply: StreamAutoClaimReply = conn.xautoclaim_options(
+            &self.stream_name,
+            &self.group_name,
+            &self.consumer_name,
+            min_idle.as_millis() as u64,
+            start,
+            StreamAutoClaimOptions::default().count(count),
+        )?;
+        if !reply.deleted_ids.is_empty() {
+            warn!(
+                "{} pending messages were trimmed from {} before they were processed",
+                reply.deleted_ids.len(),
+                self.stream_name
+            );
+        }
+        if !reply.claimed.is_empty() {
+            info!(
+                "Claimed {} messages idle for over {:?}",
+                reply.claimed.len(),
+                min_idle
+            );
+        }
+
+        let messages = self.decode_entries(&mut conn, reply.claimed, true)?;
+        Ok((reply.next_stream_id, messages))
+    }
+
+    /// Resets the idle time of messages this consumer is still working on, so they are
+    /// not claimed as stuck. Does not count as a delivery.
+    pub fn touch(&self, message_ids: &[&str]) -> RedisResult<()> {
+        if message_ids.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.client.get_connection()?;
+        let _: redis::Value = conn.xclaim_options(
+            &self.stream_name,
+            &self.group_name,
+            &self.consumer_name,
+            0,
+            message_ids,
+            StreamClaimOptions::default().with_justid(),
+        )?;
+        Ok(())
+    }
+
+    fn read_group(
+        &self,
+        id: &str,
+        options: StreamReadOptions,
+    ) -> RedisResult<(Option<String>, Messages)> {
+        let mut conn = self.client.get_connection()?;
+
+        let result: StreamReadReply = conn.xread_options(&[&self.stream_name], &[id], &options)?;
+        let entries: Vec<StreamId> = result
+            .keys
+            .into_iter()
+            .flat_map(|stream| stream.ids)
+            .collect();
+        let last_id = entries.last().map(|entry| entry.id.clone());
+
+        // Only reads of the pending list (any ID but `>`) see redelivered messages.
+        let messages = self.decode_entries(&mut conn, entries, id != ">")?;
+        Ok((last_id, messages))
+    }
+
+    /// Decodes read or claimed entries. Entries that cannot be decoded, or that were
+    /// delivered more than `max_deliveries` times, go to the dead-letter stream.
+    fn decode_entries(
+        &self,
+        conn: &mut Connection,
+        entries: Vec<StreamId>,
+        redelivered: bool,
+    ) -> RedisResult<Messages> {
+        let deliveries = if redelivered {
+            self.delivery_counts(conn, &entries)?
+        } else {
+            HashMap::new()
+        };
 
         let mut messages = Vec::new();
+        let mut skipped = Vec::new();
 
-        for stream in result.keys {
-            for message in stream.ids {
-                info!("ID: {}", message.id);
-
-                if let Some(value) = message.map.get("payload") {
-                    let payload: String = redis::from_redis_value(value)?;
-                    match serde_json::from_str::<IndexEvent>(&payload) {
-                        Ok(event) => {
-                            info!("Got IndexEvent: {:?}", event);
-                            messages.push((message.id, event));
-                        }
-                        Err(e) => {
-                            info!("Failed to deserialize IndexEvent: {}", e);
-                        }
-                    }
+        for message in entries {
+            info!("ID: {}", message.id);
+            let previous_attempts = message.get(ATTEMPTS_FIELD).unwrap_or(0);
+            if let Some(&delivered) = deliveries.get(&message.id)
+                && delivered > self.max_deliveries
+            {
+                self.move_to_dead_letter(
+                    conn,
+                    &message.id,
+                    message.get(PAYLOAD_FIELD).unwrap_or_default(),
+                    message.get(CODEC_FIELD),
+                    previous_attempts,
+                    &format!(
+                        "Delivered {} times without being processed (max {})",
+                        delivered, self.max_deliveries
+                    ),
+                )?;
+                continue;
+            }
+
+            let Some(payload) = message.get::<Vec<u8>>(PAYLOAD_FIELD) else {
+                self.move_to_dead_letter(
+                    conn,
+                    &message.id,
+                    Vec::new(),
+                    None,
+                    0,
+                    "no payload field",
+                )?;
+                continue;
+            };
+            // Entries without a codec field predate codecs and are plain JSON.
+            let header: Option<String> = message.get(CODEC_FIELD);
+            let decoded =
+                Codec::from_header(header.as_deref()).and_then(|codec| codec.decode(&payload));
+            match decoded {
+                Ok(Decoded::Event(event)) => {
+                    info!("Got IndexEvent: {:?}", event);
+                    messages.push((message.id, event));
+                }
+                Ok(Decoded::Unknown {
+                    kind,
+                    schema_version,
+                }) => {
+                    warn!(
+                        "Skipping {} event {} of schema version {}, unknown to this build",
+                        kind, message.id, schema_version
+                    );
+                    skipped.push(message.id);
+                }
+                Err(e) => {
+                    self.move_to_dead_letter(
+                        conn,
+                        &message.id,
+                        payload,
+                        header,
+                        previous_attempts,
+                        &format!("Failed to deserialize IndexEvent: {:#}", e),
+                    )?;
                 }
             }
         }
 
+        // Events this build cannot store would otherwise stay pending forever.
+        if !skipped.is_empty() {
+            let _: i64 = conn.xack(&self.stream_name, &self.group_name, &skipped)?;
+        }
+
         Ok(messages)
+    }
+
+    /// How often each entry has been delivered, from this consumer's pending list.
+    fn delivery_counts(
+        &self,
+        conn: &mut Connection,
+        entries: &[StreamId],
+    ) -> RedisResult<HashMap<String, usize>> {
+        if entries.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut pipe = redis::pipe();
+        for entry in entries {
+            pipe.xpending_consumer_count(
+                &self.stream_name,
+                &self.group_name,
+                &entry.id,
+                &entry.id,
+                1,
+                &self.consumer_name,
+            );
+        }
+        let replies: Vec<StreamPendingCountReply> = pipe.query(conn)?;
+        Ok(replies
+            .into_iter()
+            .flat_map(|reply| reply.ids)
+            .map(|pending| (pending.id, pending.times_delivered))
+            .collect())
+    }
+}
+
+/// These tests run against a real Redis server and are ignored by default:
+/// `REDIS_URL=redis://127.0.0.1:6379 cargo test -p db_processor -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forks::ForkTracker;
+    use crate::slot_feed::{self, SlotFeed};
+    use std::collections::{BTreeSet, HashSet};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use yellowstone_gRPC::types::{SlotStatus, SlotUpdate};
+
+    const GROUP: &str = "db_processor";
+
+    fn redis_url() -> String {
+        std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string())
+    }
+
+    /// A stream of its own holding `count` slot updates; returns its name and entry IDs.
+    fn stream_with_events(count: u64) -> (String, Vec<String>) {
+        let slots: Vec<SlotUpdate> = (0..count)
+            .map(|slot| slot_update(slot, slot.checked_sub(1), SlotStatus::Processed))
+            .collect();
+        stream_with_slots(&slots)
+    }
+
+    fn slot_update(slot: u64, parent: Option<u64>, status: SlotStatus) -> SlotUpdate {
+        SlotUpdate {
+            slot,
+            parent,
+            status,
+            dead_error: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn stream_with_slots(slots: &[SlotUpdate]) -> (String, Vec<String>) {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let stream_name = format!("db_processor_test_{}_{}", std::process::id(), nanos);
+        let mut conn = Client::open(redis_url()).unwrap().get_connection().unwrap();
+        let codec = Codec::default();
+        let ids = slots
+            .iter()
+            .map(|slot| {
+                let event = IndexEvent::Slot(slot.clone());
+                let fields = [
+                    (PAYLOAD_FIELD, codec.encode(&event).unwrap()),
+                    (CODEC_FIELD, codec.header().into_bytes()),
+                ];
+                conn.xadd(&stream_name, "*", &fields).unwrap()
+            })
+            .collect();
+        (stream_name, ids)
+    }
+
+    fn processor(stream_name: &str, consumer_name: &str) -> RedisConsumer {
+        let consumer =
+            RedisConsumer::new(&redis_url(), stream_name, GROUP, consumer_name, 5).unwrap();
+        consumer.create_consumer_group().unwrap();
+        consumer
+    }
+
+    fn delete_stream(stream_name: &str) {
+        let mut conn = Client::open(redis_url()).unwrap().get_connection().unwrap();
+        let _: () = conn
+            .del(&[
+                stream_name,
+                &dead_letter::stream_name(stream_name),
+                &slot_feed::stream_name(stream_name),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server at REDIS_URL"]
+    fn processors_share_one_stream() {
+        // Three replicas with their own consumer names in one group: every message is
+        // delivered to exactly one of them, and each of them gets a share.
+        let (stream_name, ids) = stream_with_events(30);
+        let processors: Vec<RedisConsumer> = (0..3)
+            .map(|replica| processor(&stream_name, &format!("replica-{}", replica)))
+            .collect();
+
+        let mut delivered_to = HashMap::<String, usize>::new();
+        loop {
+            let mut read = 0;
+            for (replica, processor) in processors.iter().enumerate() {
+                let messages = processor.consume_message(4, 10).unwrap();
+                read += messages.len();
+                let message_ids: Vec<String> = messages.into_iter().map(|(id, _)| id).collect();
+                for message_id in &message_ids {
+                    assert!(
+                        delivered_to.insert(message_id.clone(), replica).is_none(),
+                        "{} was delivered twice",
+                        message_id
+                    );
+                }
+                processor.acknowledge(&message_ids).unwrap();
+            }
+            if read == 0 {
+                break;
+            }
+        }
+
+        let delivered: BTreeSet<&String> = delivered_to.keys().collect();
+        assert_eq!(delivered, ids.iter().collect());
+        let replicas: HashSet<usize> = delivered_to.values().copied().collect();
+        assert_eq!(replicas.len(), processors.len());
+        for processor in &processors {
+            assert!(processor.deregister().unwrap());
+        }
+        delete_stream(&stream_name);
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server at REDIS_URL"]
+    fn every_processor_tracks_every_slot() {
+        // Slot 12 forks off 10 and is abandoned once 13 is finalized. Each replica only
+        // consumes some of these updates, but all of them must reach its fork tracker.
+        let (stream_name, _) = stream_with_slots(&[
+            slot_update(10, Some(9), SlotStatus::Finalized),
+            slot_update(11, Some(10), SlotStatus::Processed),
+            slot_update(12, Some(10), SlotStatus::Processed),
+            slot_update(13, Some(11), SlotStatus::Processed),
+            slot_update(11, None, SlotStatus::Confirmed),
+            slot_update(13, None, SlotStatus::Confirmed),
+            slot_update(11, None, SlotStatus::Finalized),
+            slot_update(13, None, SlotStatus::Finalized),
+        ]);
+        let mut replicas: Vec<(RedisConsumer, SlotFeed)> = (0..3)
+            .map(|replica| {
+                let feed =
+                    SlotFeed::new(&redis_url(), &slot_feed::stream_name(&stream_name)).unwrap();
+                (
+                    processor(&stream_name, &format!("replica-{}", replica)),
+                    feed,
+                )
+            })
+            .collect();
+
+        loop {
+            let mut read = 0;
+            for (processor, feed) in &replicas {
+                let messages = processor.consume_message(2, 10).unwrap();
+                read += messages.len();
+                let slots: Vec<&SlotUpdate> = messages
+                    .iter()
+                    .filter_map(|(_, event)| match event {
+                        IndexEvent::Slot(slot) => Some(slot),
+                        _ => None,
+                    })
+                    .collect();
+                feed.share(&slots).unwrap();
+                let message_ids: Vec<String> = messages.into_iter().map(|(id, _)| id).collect();
+                processor.acknowledge(&message_ids).unwrap();
+            }
+            if read == 0 {
+                break;
+            }
+        }
+
+        for (processor, feed) in &mut replicas {
+            let slots = feed.read().unwrap();
+            assert_eq!(slots.len(), 8);
+            let mut forks = ForkTracker::default();
+            let orphaned: Vec<u64> = slots.iter().flat_map(|slot| forks.on_slot(slot)).collect();
+            assert_eq!(orphaned, [12]);
+            assert!(feed.read().unwrap().is_empty());
+            assert!(processor.deregister().unwrap());
+        }
+        delete_stream(&stream_name);
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server at REDIS_URL"]
+    fn idle_messages_move_to_a_live_processor() {
+        let (stream_name, ids) = stream_with_events(5);
+        let crashed = processor(&stream_name, "crashed");
+        let live = processor(&stream_name, "live");
+
+        // Read but never acknowledged, as if the processor died mid-batch.
+        assert_eq!(crashed.consume_message(5, 10).unwrap().len(), 5);
+        let (next_start, claimed) = live.claim_idle("0-0", Duration::ZERO, 100).unwrap();
+        assert_eq!(next_start, "0-0");
+        let claimed_ids: Vec<String> = claimed.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(claimed_ids, ids);
+
+        // The crashed consumer has nothing pending now and is removed from the group.
+        live.acknowledge(&claimed_ids).unwrap();
+        assert_eq!(
+            live.remove_idle_consumers(Duration::ZERO).unwrap(),
+            ["crashed"]
+        );
+        assert!(live.deregister().unwrap());
+        delete_stream(&stream_name);
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server at REDIS_URL"]
+    fn deregistering_keeps_consumers_with_pending_messages() {
+        let (stream_name, _) = stream_with_events(1);
+        let processor = processor(&stream_name, "replica");
+
+        let messages = processor.consume_message(1, 10).unwrap();
+        assert!(!processor.deregister().unwrap());
 
-        // Extract the messages and message_id from result
+        let message_ids: Vec<String> = messages.into_iter().map(|(id, _)| id).collect();
+        processor.acknowledge(&message_ids).unwrap();
+        assert!(processor.deregister().unwrap());
+        delete_stream(&stream_name);
     }
 }
@@ -0,0 +1,366 @@
+use redis::{ErrorKind, RedisError, RedisResult};
+use scylla::transport::errors::{DbError, NewSessionError, QueryError};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use yellowstone_gRPC::reconnect::{Backoff, ReconnectPolicy};
+
+/// How transient ScyllaDB and Redis failures are retried. An operation that fails with
+/// a timeout, an unavailable or overloaded node or a dropped connection is retried with
+/// exponential backoff up to `max_attempts` times. When ScyllaDB still fails after that,
+/// the circuit breaker opens and the processor stops consuming for `breaker_cooldown`
+/// before it probes ScyllaDB again.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_attempts: u32,
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_attempts: 5,
+            breaker_cooldown: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self) -> Backoff {
+        Backoff::new(ReconnectPolicy {
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            multiplier: 2.0,
+            // The first attempt is not a retry.
+            max_attempts: Some(self.max_attempts.saturating_sub(1)),
+        })
+    }
+}
+
+/// Stops the processor from consuming while ScyllaDB is unhealthy. It opens when an
+/// operation runs out of retries and lets a probe through once the cooldown passed.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    cooldown: Duration,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            opened_at: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.opened_at.is_some()
+    }
+
+    /// Time left until the next probe; zero once it is due.
+    pub fn until_probe(&self) -> Duration {
+        self.opened_at
+            .map(|at| self.cooldown.saturating_sub(at.elapsed()))
+            .unwrap_or_default()
+    }
+
+    /// Opens the breaker, or restarts the cooldown after a failed probe.
+    pub fn trip(&mut self) {
+        self.opened_at = Some(Instant::now());
+    }
+
+    pub fn close(&mut self) {
+        self.opened_at = None;
+    }
+}
+
+/// Whether a ScyllaDB error may go away by waiting: timeouts, broken connections and
+/// nodes that are unavailable, overloaded or still bootstrapping.
+pub fn is_transient_scylla(error: &(dyn Error + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<QueryError>() {
+        return match error {
+            QueryError::DbError(error, _) => is_transient_db(error),
+            QueryError::IoError(_)
+            | QueryError::TimeoutError
+            | QueryError::RequestTimeout(_)
+            | QueryError::TooManyOrphanedStreamIds(_)
+            | QueryError::UnableToAllocStreamId => true,
+            _ => false,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<NewSessionError>() {
+        return match error {
+            NewSessionError::DbError(error, _) => is_transient_db(error),
+            NewSessionError::FailedToResolveAnyHostname(_)
+            | NewSessionError::IoError(_)
+            | NewSessionError::TimeoutError
+            | NewSessionError::RequestTimeout(_)
+            | NewSessionError::TooManyOrphanedStreamIds(_)
+            | NewSessionError::UnableToAllocStreamId => true,
+            _ => false,
+        };
+    }
+    false
+}
+
+fn is_transient_db(error: &DbError) -> bool {
+    matches!(
+        error,
+        DbError::Unavailable { .. }
+            | DbError::Overloaded
+            | DbError::IsBootstrapping
+            | DbError::ReadTimeout { .. }
+            | DbError::WriteTimeout { .. }
+            | DbError::RateLimitReached { .. }
+    )
+}
+
+/// Whether a Redis error may go away by waiting: timeouts, refused or dropped
+/// connections and a server that is loading or failing over.
+pub fn is_transient_redis(error: &RedisError) -> bool {
+    error.is_timeout()
+        || error.is_io_error()
+        || error.is_connection_refusal()
+        || error.is_connection_dropped()
+        || matches!(
+            error.kind(),
+            ErrorKind::BusyLoadingError
+                | ErrorKind::TryAgain
+                | ErrorKind::ClusterDown
+                | ErrorKind::MasterDown
+        )
+}
+
+/// Runs a ScyllaDB operation, retrying transient failures. Returns the last error once
+/// the retries are used up or the error is not transient; check it with
+/// [`is_transient_scylla`] to tell the two apart.
+pub async fn scylla<T>(
+    policy: &RetryPolicy,
+    what: &str,
+    mut operation: impl AsyncFnMut() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    let mut backoff = policy.backoff();
+    loop {
+        let error = match operation().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        if !is_transient_scylla(error.as_ref()) {
+            return Err(error);
+        }
+        let Some(delay) = backoff.next_delay() else {
+            return Err(error);
+        };
+        warn!(
+            "Transient ScyllaDB error {} (attempt {}), retrying in {:?}: {}",
+            what,
+            backoff.attempt(),
+            delay,
+            error
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Runs a Redis command, retrying transient failures.
+pub async fn redis<T>(
+    policy: &RetryPolicy,
+    what: &str,
+    mut operation: impl FnMut() -> RedisResult<T>,
+) -> RedisResult<T> {
+    let mut backoff = policy.backoff();
+    loop {
+        let error = match operation() {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        if !is_transient_redis(&error) {
+            return Err(error);
+        }
+        let Some(delay) = backoff.next_delay() else {
+            return Err(error);
+        };
+        warn!(
+            "Transient Redis error {} (attempt {}), retrying in {:?}: {}",
+            what,
+            backoff.attempt(),
+            delay,
+            error
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            max_attempts,
+            breaker_cooldown: Duration::ZERO,
+        }
+    }
+
+    fn dropped_connection() -> RedisError {
+        RedisError::from(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+    }
+
+    #[test]
+    fn classifies_scylla_errors() {
+        let transient: [Box<dyn Error>; 5] = [
+            Box::new(QueryError::TimeoutError),
+            Box::new(QueryError::IoError(Arc::new(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            )))),
+            Box::new(QueryError::DbError(DbError::Overloaded, String::new())),
+            Box::new(NewSessionError::TimeoutError),
+            Box::new(NewSessionError::DbError(
+                DbError::IsBootstrapping,
+                String::new(),
+            )),
+        ];
+        for error in &transient {
+            assert!(is_transient_scylla(error.as_ref()), "{}", error);
+        }
+
+        let permanent: [Box<dyn Error>; 4] = [
+            Box::new(QueryError::DbError(DbError::SyntaxError, String::new())),
+            Box::new(QueryError::InvalidMessage("bad frame".to_string())),
+            Box::new(NewSessionError::DbError(DbError::Invalid, String::new())),
+            "not a ScyllaDB error".into(),
+        ];
+        for error in &permanent {
+            assert!(!is_transient_scylla(error.as_ref()), "{}", error);
+        }
+    }
+
+    #[test]
+    fn classifies_redis_errors() {
+        assert!(is_transient_redis(&dropped_connection()));
+        assert!(is_transient_redis(&RedisError::from((
+            ErrorKind::BusyLoadingError,
+            "loading"
+        ))));
+        assert!(is_transient_redis(&RedisError::from((
+            ErrorKind::TryAgain,
+            "try again"
+        ))));
+        assert!(!is_transient_redis(&RedisError::from((
+            ErrorKind::TypeError,
+            "wrong type"
+        ))));
+        assert!(!is_transient_redis(&RedisError::from((
+            ErrorKind::ResponseError,
+            "unknown command"
+        ))));
+    }
+
+    #[test]
+    fn opens_probes_and_closes_the_breaker() {
+        let mut breaker = CircuitBreaker::new(Duration::from_secs(60));
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.until_probe(), Duration::ZERO);
+
+        breaker.trip();
+        assert!(breaker.is_open());
+        assert!(breaker.until_probe() > Duration::from_secs(59));
+
+        breaker.close();
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.until_probe(), Duration::ZERO);
+
+        // Once the cooldown passed, the breaker stays open but lets a probe through.
+        let mut breaker = CircuitBreaker::new(Duration::ZERO);
+        breaker.trip();
+        assert!(breaker.is_open());
+        assert_eq!(breaker.until_probe(), Duration::ZERO);
+        // A failed probe opens it again.
+        breaker.trip();
+        assert!(breaker.is_open());
+        breaker.close();
+        assert!(!breaker.is_open());
+    }
+
+    #[tokio::test]
+    async fn retries_scylla_up_to_the_attempt_limit() {
+        let mut calls = 0;
+        let result: Result<(), _> = scylla(&policy(3), "testing", async || {
+            calls += 1;
+            Err(Box::new(QueryError::TimeoutError) as Box<dyn Error>)
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result = scylla(&policy(3), "testing", async || {
+            calls += 1;
+            if calls < 3 {
+                Err(Box::new(QueryError::TimeoutError) as Box<dyn Error>)
+            } else {
+                Ok(calls)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_permanent_scylla_errors() {
+        let mut calls = 0;
+        let result: Result<(), _> = scylla(&policy(3), "testing", async || {
+            calls += 1;
+            Err(
+                Box::new(QueryError::DbError(DbError::SyntaxError, String::new()))
+                    as Box<dyn Error>,
+            )
+        })
+        .await;
+        assert!(!is_transient_scylla(result.unwrap_err().as_ref()));
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn retries_redis_up_to_the_attempt_limit() {
+        let mut calls = 0;
+        let result: RedisResult<()> = redis(&policy(4), "testing", || {
+            calls += 1;
+            Err(dropped_connection())
+        })
+        .await;
+        assert!(result.unwrap_err().is_io_error());
+        assert_eq!(calls, 4);
+
+        let mut calls = 0;
+        let result = redis(&policy(4), "testing", || {
+            calls += 1;
+            if calls < 2 {
+                Err(dropped_connection())
+            } else {
+                Ok(calls)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_permanent_redis_errors() {
+        let mut calls = 0;
+        let result: RedisResult<()> = redis(&policy(4), "testing", || {
+            calls += 1;
+            Err(RedisError::from((ErrorKind::TypeError, "wrong type")))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TypeError);
+        assert_eq!(calls, 1);
+    }
+}
@@ -1,26 +1,57 @@
 use std::error::Error;
 use std::sync::Arc;
 
-use crate::scylla_types::{FromScyllaDb, ScyllaAccount, ScyllaTransaction, ToScyllaDb};
+use crate::forks::OrphanPolicy;
+use crate::scylla_types::{
+    FromScyllaDb, ScyllaAccount, ScyllaBlock, ScyllaSlot, ScyllaTokenBalanceChange,
+    ScyllaTransaction, ScyllaTransactionStatus, ToScyllaDb,
+};
+use futures::StreamExt;
+use scylla::frame::value::MaybeUnset;
+use scylla::query::Query;
 use scylla::{Session, SessionBuilder};
-use yellowstone_gRPC::types::{SolanaAccount, SolanaTransaction};
+use tracing::info;
+use yellowstone_gRPC::types::{
+    SlotUpdate, SolanaAccount, SolanaBlock, SolanaTransaction, TokenBalanceChange,
+    TransactionStatus,
+};
+
+/// Rows fetched per page when looking up what an orphaned slot wrote.
+const ORPHAN_PAGE_SIZE: i32 = 1_000;
 
 pub struct ScyllaWriter {
     session: Arc<Session>,
     keyspace: String,
     accounts_table: String,
+    account_writes_table: String,
+    account_slots_table: String,
     transactions_table: String,
+    slots_table: String,
+    token_balance_changes_table: String,
+    blocks_table: String,
+    transaction_statuses_table: String,
     batch_size: usize,
     account_batch: Vec<ScyllaAccount>,
     transaction_batch: Vec<ScyllaTransaction>,
+    token_balance_change_batch: Vec<ScyllaTokenBalanceChange>,
+    slot_batch: Vec<ScyllaSlot>,
+    block_batch: Vec<ScyllaBlock>,
+    transaction_status_batch: Vec<ScyllaTransactionStatus>,
 }
 
 impl ScyllaWriter {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         nodes: Vec<&str>,
         keyspace: &str,
         accounts_table: &str,
+        account_writes_table: &str,
+        account_slots_table: &str,
         transactions_table: &str,
+        slots_table: &str,
+        token_balance_changes_table: &str,
+        blocks_table: &str,
+        transaction_statuses_table: &str,
         batch_size: usize,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let session: Session = SessionBuilder::new().known_nodes(nodes).build().await?;
@@ -31,15 +62,39 @@ impl ScyllaWriter {
             session,
             keyspace: keyspace.to_string(),
             accounts_table: accounts_table.to_string(),
+            account_writes_table: account_writes_table.to_string(),
+            account_slots_table: account_slots_table.to_string(),
             transactions_table: transactions_table.to_string(),
+            slots_table: slots_table.to_string(),
+            token_balance_changes_table: token_balance_changes_table.to_string(),
+            blocks_table: blocks_table.to_string(),
+            transaction_statuses_table: transaction_statuses_table.to_string(),
             batch_size,
             account_batch: Vec::with_capacity(batch_size),
             transaction_batch: Vec::with_capacity(batch_size),
+            token_balance_change_batch: Vec::new(),
+            slot_batch: Vec::with_capacity(batch_size),
+            block_batch: Vec::with_capacity(batch_size),
+            transaction_status_batch: Vec::with_capacity(batch_size),
         };
 
         Ok(writer)
     }
 
+    /// Creates the keyspace and every table the processor writes to.
+    pub async fn create_schema(&self) -> Result<(), Box<dyn Error>> {
+        self.create_keyspace().await?;
+        self.create_accounts_table().await?;
+        self.create_account_writes_table().await?;
+        self.create_account_slots_table().await?;
+        self.create_transactions_table().await?;
+        self.create_token_balance_changes_table().await?;
+        self.create_slots_table().await?;
+        self.create_blocks_table().await?;
+        self.create_transaction_statuses_table().await?;
+        Ok(())
+    }
+
     pub async fn create_keyspace(&self) -> Result<(), Box<dyn Error>> {
         let create_keyspace_query = format!(
             r#"
@@ -76,7 +131,9 @@ impl ScyllaWriter {
                 write_version bigint,
                 slot bigint,
                 txn_signature text,
-                timestamp_ms bigint
+                timestamp_ms bigint,
+                ingested_at_ms bigint,
+                orphaned boolean
             );
             "#,
             self.keyspace, self.accounts_table
@@ -84,6 +141,59 @@ impl ScyllaWriter {
 
         self.session.query_unpaged(create_table_query, &[]).await?;
 
+        // Tables created by earlier versions predate these columns.
+        self.add_column_if_missing(&self.accounts_table, "ingested_at_ms", "bigint")
+            .await?;
+        self.add_column_if_missing(&self.accounts_table, "orphaned", "boolean")
+            .await?;
+
+        Ok(())
+    }
+
+    /// Every account write, newest first per account, so orphaning a slot can restore
+    /// the write it replaced.
+    pub async fn create_account_writes_table(&self) -> Result<(), Box<dyn Error>> {
+        let create_table_query = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {}.{} (
+                pubkey text,
+                slot bigint,
+                write_version bigint,
+                lamports bigint,
+                owner text,
+                executable boolean,
+                rent_epoch bigint,
+                data text,
+                txn_signature text,
+                timestamp_ms bigint,
+                ingested_at_ms bigint,
+                orphaned boolean,
+                PRIMARY KEY ((pubkey), slot, write_version)
+            ) WITH CLUSTERING ORDER BY (slot DESC, write_version DESC);
+            "#,
+            self.keyspace, self.account_writes_table
+        );
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+
+        Ok(())
+    }
+
+    /// The accounts written in each slot, so orphaning a slot does not scan `accounts`.
+    pub async fn create_account_slots_table(&self) -> Result<(), Box<dyn Error>> {
+        let create_table_query = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {}.{} (
+                slot bigint,
+                pubkey text,
+                PRIMARY KEY ((slot), pubkey)
+            );
+            "#,
+            self.keyspace, self.account_slots_table
+        );
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+
         Ok(())
     }
 
@@ -96,14 +206,32 @@ impl ScyllaWriter {
                 is_vote boolean,
                 tx_index bigint,
                 success boolean,
+                error_kind text,
+                error_custom_code bigint,
+                error_json text,
                 fee bigint,
+                priority_fee bigint,
+                base_fee bigint,
                 compute_units_consumed bigint,
+                cost_units bigint,
+                compute_budget_json text,
                 instructions_json text,
+                inner_instructions_json text,
                 account_keys_json text,
+                is_writable_json text,
+                is_signer_json text,
+                version int,
                 log_messages_json text,
                 pre_balances_json text,
                 post_balances_json text,
-                timestamp_ms bigint
+                pre_token_balances_json text,
+                post_token_balances_json text,
+                token_balance_changes_json text,
+                rewards_json text,
+                return_data_json text,
+                timestamp_ms bigint,
+                ingested_at_ms bigint,
+                orphaned boolean
             );
             "#,
             self.keyspace, self.transactions_table
@@ -111,6 +239,42 @@ impl ScyllaWriter {
 
         self.session.query_unpaged(create_table_query, &[]).await?;
 
+        // Tables created by earlier versions predate these columns.
+        self.add_column_if_missing(&self.transactions_table, "ingested_at_ms", "bigint")
+            .await?;
+        self.add_column_if_missing(&self.transactions_table, "orphaned", "boolean")
+            .await?;
+        self.add_column_if_missing(&self.transactions_table, "inner_instructions_json", "text")
+            .await?;
+        self.add_column_if_missing(&self.transactions_table, "is_writable_json", "text")
+            .await?;
+        self.add_column_if_missing(&self.transactions_table, "is_signer_json", "text")
+            .await?;
+        self.add_column_if_missing(&self.transactions_table, "version", "int")
+            .await?;
+        for column in [
+            "error_custom_code",
+            "priority_fee",
+            "base_fee",
+            "cost_units",
+        ] {
+            self.add_column_if_missing(&self.transactions_table, column, "bigint")
+                .await?;
+        }
+        for column in [
+            "pre_token_balances_json",
+            "post_token_balances_json",
+            "token_balance_changes_json",
+            "error_kind",
+            "error_json",
+            "compute_budget_json",
+            "rewards_json",
+            "return_data_json",
+        ] {
+            self.add_column_if_missing(&self.transactions_table, column, "text")
+                .await?;
+        }
+
         // Create index on slot for efficient slot-based queries
         let create_slot_index = format!(
             "CREATE INDEX IF NOT EXISTS ON {}.{} (slot);",
@@ -121,6 +285,141 @@ impl ScyllaWriter {
         Ok(())
     }
 
+    /// Token balance changes partitioned by owner, newest slot first, so a wallet's swaps
+    /// can be listed without decoding instructions.
+    pub async fn create_token_balance_changes_table(&self) -> Result<(), Box<dyn Error>> {
+        let create_table_query = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {}.{} (
+                owner text,
+                slot bigint,
+                signature text,
+                mint text,
+                amount_delta varint,
+                ui_amount_delta double,
+                decimals int,
+                timestamp_ms bigint,
+                orphaned boolean,
+                PRIMARY KEY ((owner), slot, signature, mint)
+            ) WITH CLUSTERING ORDER BY (slot DESC, signature ASC, mint ASC);
+            "#,
+            self.keyspace, self.token_balance_changes_table
+        );
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+
+        Ok(())
+    }
+
+    /// One row per slot. Each status update fills its own `<status>_at_ms` column and
+    /// overwrites `status`, so a row shows how far the slot progressed and when.
+    pub async fn create_slots_table(&self) -> Result<(), Box<dyn Error>> {
+        let create_table_query = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {}.{} (
+                slot bigint PRIMARY KEY,
+                parent bigint,
+                status text,
+                dead_error text,
+                processed_at_ms bigint,
+                confirmed_at_ms bigint,
+                finalized_at_ms bigint,
+                dead_at_ms bigint,
+                orphaned boolean
+            );
+            "#,
+            self.keyspace, self.slots_table
+        );
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+
+        // Tables created by earlier versions predate these columns.
+        self.add_column_if_missing(&self.slots_table, "orphaned", "boolean")
+            .await?;
+
+        Ok(())
+    }
+
+    /// One row per slot, written from block metas and, when subscribed, full blocks.
+    pub async fn create_blocks_table(&self) -> Result<(), Box<dyn Error>> {
+        let create_table_query = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {}.{} (
+                slot bigint PRIMARY KEY,
+                blockhash text,
+                parent_slot bigint,
+                parent_blockhash text,
+                block_height bigint,
+                block_time_ms bigint,
+                executed_transaction_count bigint,
+                entries_count bigint,
+                updated_account_count bigint,
+                rewards_json text,
+                entries_json text,
+                ingested_at_ms bigint,
+                orphaned boolean
+            );
+            "#,
+            self.keyspace, self.blocks_table
+        );
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Compact per-signature outcome written in `TRANSACTION_MODE=status`.
+    pub async fn create_transaction_statuses_table(&self) -> Result<(), Box<dyn Error>> {
+        let create_table_query = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {}.{} (
+                signature text PRIMARY KEY,
+                slot bigint,
+                tx_index bigint,
+                is_vote boolean,
+                success boolean,
+                error_kind text,
+                error_custom_code bigint,
+                error_json text,
+                timestamp_ms bigint,
+                ingested_at_ms bigint,
+                orphaned boolean
+            );
+            "#,
+            self.keyspace, self.transaction_statuses_table
+        );
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+
+        let create_slot_index = format!(
+            "CREATE INDEX IF NOT EXISTS ON {}.{} (slot);",
+            self.keyspace, self.transaction_statuses_table
+        );
+        self.session.query_unpaged(create_slot_index, &[]).await?;
+
+        Ok(())
+    }
+
+    /// `CREATE TABLE IF NOT EXISTS` leaves existing tables alone, so new columns are
+    /// added with `ALTER TABLE`, ignoring the error for columns that already exist.
+    async fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        column_type: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let alter_query = format!(
+            "ALTER TABLE {}.{} ADD {} {}",
+            self.keyspace, table, column, column_type
+        );
+
+        match self.session.query_unpaged(alter_query, &[]).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.to_string().contains("conflicts with an existing column") => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub async fn flush_account_batch(&mut self) -> Result<(), Box<dyn Error>> {
         if self.account_batch.is_empty() {
             return Ok(());
@@ -128,29 +427,23 @@ impl ScyllaWriter {
 
         let start_time = std::time::Instant::now();
 
-        let insert_query = format!(
-            "INSERT INTO {}.{} (pubkey, lamports, owner, executable, rent_epoch, data, write_version, slot, txn_signature, timestamp_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            self.keyspace, self.accounts_table
+        let insert_write = format!(
+            "INSERT INTO {}.{} (pubkey, lamports, owner, executable, rent_epoch, data, write_version, slot, txn_signature, timestamp_ms, ingested_at_ms, orphaned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, false)",
+            self.keyspace, self.account_writes_table
+        );
+        let insert_slot = format!(
+            "INSERT INTO {}.{} (slot, pubkey) VALUES (?, ?)",
+            self.keyspace, self.account_slots_table
         );
 
         for account in &self.account_batch {
             self.session
-                .query_unpaged(
-                    insert_query.as_str(),
-                    (
-                        &account.pubkey,
-                        account.lamports,
-                        &account.owner,
-                        account.executable,
-                        account.rent_epoch,
-                        &account.data,
-                        account.write_version,
-                        account.slot,
-                        &account.txn_signature,
-                        account.timestamp_ms,
-                    ),
-                )
+                .query_unpaged(insert_write.as_str(), account)
+                .await?;
+            self.session
+                .query_unpaged(insert_slot.as_str(), (account.slot, &account.pubkey))
                 .await?;
+            self.upsert_account(account).await?;
         }
 
         let duration = start_time.elapsed();
@@ -165,6 +458,18 @@ impl ScyllaWriter {
         Ok(())
     }
 
+    /// Writes `account` as the latest state of its pubkey.
+    async fn upsert_account(&self, account: &ScyllaAccount) -> Result<(), Box<dyn Error>> {
+        let insert_query = format!(
+            "INSERT INTO {}.{} (pubkey, lamports, owner, executable, rent_epoch, data, write_version, slot, txn_signature, timestamp_ms, ingested_at_ms, orphaned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, false)",
+            self.keyspace, self.accounts_table
+        );
+        self.session
+            .query_unpaged(insert_query.as_str(), account)
+            .await?;
+        Ok(())
+    }
+
     pub async fn flush_transaction_batch(&mut self) -> Result<(), Box<dyn Error>> {
         if self.transaction_batch.is_empty() {
             return Ok(());
@@ -172,29 +477,138 @@ impl ScyllaWriter {
 
         let start_time = std::time::Instant::now();
 
+        // Bind markers are matched to `ScyllaTransaction` fields by column name.
         let insert_query = format!(
-            "INSERT INTO {}.{} (signature, slot, is_vote, tx_index, success, fee, compute_units_consumed, instructions_json, account_keys_json, log_messages_json, pre_balances_json, post_balances_json, timestamp_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO {}.{} (signature, slot, is_vote, tx_index, success, error_kind, error_custom_code, error_json, fee, priority_fee, base_fee, compute_units_consumed, cost_units, compute_budget_json, instructions_json, inner_instructions_json, account_keys_json, is_writable_json, is_signer_json, version, log_messages_json, pre_balances_json, post_balances_json, pre_token_balances_json, post_token_balances_json, token_balance_changes_json, rewards_json, return_data_json, timestamp_ms, ingested_at_ms, orphaned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, false)",
             self.keyspace, self.transactions_table
         );
 
         for transaction in &self.transaction_batch {
+            self.session
+                .query_unpaged(insert_query.as_str(), transaction)
+                .await?;
+        }
+
+        let duration = start_time.elapsed();
+        println!(
+            "Inserted {} transactions in {:?} (avg: {:.2}ms per transaction)",
+            self.transaction_batch.len(),
+            duration,
+            duration.as_millis() as f64 / self.transaction_batch.len() as f64
+        );
+
+        self.transaction_batch.clear();
+
+        let insert_change_query = format!(
+            "INSERT INTO {}.{} (owner, mint, slot, signature, amount_delta, ui_amount_delta, decimals, timestamp_ms, orphaned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, false)",
+            self.keyspace, self.token_balance_changes_table
+        );
+        for change in &self.token_balance_change_batch {
+            self.session
+                .query_unpaged(insert_change_query.as_str(), change)
+                .await?;
+        }
+        self.token_balance_change_batch.clear();
+
+        Ok(())
+    }
+
+    pub async fn flush_slot_batch(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.slot_batch.is_empty() {
+            return Ok(());
+        }
+
+        let start_time = std::time::Instant::now();
+
+        // Updates arrive out of order, e.g. a late processed after finalized. The status
+        // is written with its rank as the write timestamp, so it only ever moves forward
+        // however the updates are ordered, without a lightweight transaction.
+        let update_status = format!(
+            "UPDATE {}.{} USING TIMESTAMP ? SET status = ? WHERE slot = ?",
+            self.keyspace, self.slots_table
+        );
+        for slot in &self.slot_batch {
+            // Unset rather than null so a later update without a parent or error does
+            // not clear what an earlier one wrote.
+            let insert_query = format!(
+                "INSERT INTO {}.{} (slot, parent, dead_error, {}_at_ms) VALUES (?, ?, ?, ?)",
+                self.keyspace, self.slots_table, slot.status
+            );
+            let parent = match slot.parent {
+                Some(parent) => MaybeUnset::Set(parent),
+                None => MaybeUnset::Unset,
+            };
+            let dead_error = match &slot.dead_error {
+                Some(dead_error) => MaybeUnset::Set(dead_error),
+                None => MaybeUnset::Unset,
+            };
+
+            self.session
+                .query_unpaged(
+                    insert_query,
+                    (slot.slot, parent, dead_error, slot.timestamp_ms),
+                )
+                .await?;
+            self.session
+                .query_unpaged(
+                    update_status.as_str(),
+                    (slot.status_rank, &slot.status, slot.slot),
+                )
+                .await?;
+        }
+
+        let duration = start_time.elapsed();
+        println!(
+            "Inserted {} slot updates in {:?} (avg: {:.2}ms per slot update)",
+            self.slot_batch.len(),
+            duration,
+            duration.as_millis() as f64 / self.slot_batch.len() as f64
+        );
+
+        self.slot_batch.clear();
+        Ok(())
+    }
+
+    pub async fn flush_block_batch(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.block_batch.is_empty() {
+            return Ok(());
+        }
+
+        let start_time = std::time::Instant::now();
+
+        let insert_query = format!(
+            "INSERT INTO {}.{} (slot, blockhash, parent_slot, parent_blockhash, block_height, block_time_ms, executed_transaction_count, entries_count, updated_account_count, rewards_json, entries_json, ingested_at_ms, orphaned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, false)",
+            self.keyspace, self.blocks_table
+        );
+
+        for block in &self.block_batch {
+            // A block meta carries neither entries nor the account count; leave them
+            // unset so it does not clear what the full block of the same slot wrote.
+            let updated_account_count = match block.updated_account_count {
+                Some(count) => MaybeUnset::Set(count),
+                None => MaybeUnset::Unset,
+            };
+            let entries_json = match &block.entries_json {
+                Some(entries_json) => MaybeUnset::Set(entries_json),
+                None => MaybeUnset::Unset,
+            };
+
             self.session
                 .query_unpaged(
                     insert_query.as_str(),
                     (
-                        &transaction.signature,
-                        transaction.slot,
-                        transaction.is_vote,
-                        transaction.tx_index,
-                        transaction.success,
-                        transaction.fee,
-                        transaction.compute_units_consumed,
-                        &transaction.instructions_json,
-                        &transaction.account_keys_json,
-                        &transaction.log_messages_json,
-                        &transaction.pre_balances_json,
-                        &transaction.post_balances_json,
-                        transaction.timestamp_ms,
+                        block.slot,
+                        &block.blockhash,
+                        block.parent_slot,
+                        &block.parent_blockhash,
+                        block.block_height,
+                        block.block_time_ms,
+                        block.executed_transaction_count,
+                        block.entries_count,
+                        updated_account_count,
+                        &block.rewards_json,
+                        entries_json,
+                        block.ingested_at_ms,
                     ),
                 )
                 .await?;
@@ -202,13 +616,265 @@ impl ScyllaWriter {
 
         let duration = start_time.elapsed();
         println!(
-            "Inserted {} transactions in {:?} (avg: {:.2}ms per transaction)",
-            self.transaction_batch.len(),
+            "Inserted {} blocks in {:?} (avg: {:.2}ms per block)",
+            self.block_batch.len(),
             duration,
-            duration.as_millis() as f64 / self.transaction_batch.len() as f64
+            duration.as_millis() as f64 / self.block_batch.len() as f64
         );
 
-        self.transaction_batch.clear();
+        self.block_batch.clear();
+        Ok(())
+    }
+
+    pub async fn flush_transaction_status_batch(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.transaction_status_batch.is_empty() {
+            return Ok(());
+        }
+
+        let start_time = std::time::Instant::now();
+
+        // Bind markers are matched to `ScyllaTransactionStatus` fields by column name.
+        let insert_query = format!(
+            "INSERT INTO {}.{} (signature, slot, tx_index, is_vote, success, error_kind, error_custom_code, error_json, timestamp_ms, ingested_at_ms, orphaned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, false)",
+            self.keyspace, self.transaction_statuses_table
+        );
+
+        for status in &self.transaction_status_batch {
+            self.session
+                .query_unpaged(insert_query.as_str(), status)
+                .await?;
+        }
+
+        let duration = start_time.elapsed();
+        println!(
+            "Inserted {} transaction statuses in {:?} (avg: {:.2}ms per status)",
+            self.transaction_status_batch.len(),
+            duration,
+            duration.as_millis() as f64 / self.transaction_status_batch.len() as f64
+        );
+
+        self.transaction_status_batch.clear();
+        Ok(())
+    }
+
+    /// Marks or deletes the transactions and account writes of an orphaned slot, and
+    /// flags the slot and its block. Account rows are only touched while they still hold the
+    /// orphaned slot's write, so a newer canonical write is never removed.
+    pub async fn orphan_slot(&self, slot: u64, policy: OrphanPolicy) -> Result<(), Box<dyn Error>> {
+        let slot = slot as i64;
+
+        let select_signatures = format!(
+            "SELECT signature, token_balance_changes_json FROM {}.{} WHERE slot = ?",
+            self.keyspace, self.transactions_table
+        );
+        let signatures = self
+            .session
+            .query_unpaged(select_signatures, (slot,))
+            .await?
+            .rows_typed::<(String, Option<String>)>()?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let orphan_transaction = match policy {
+            OrphanPolicy::Mark => format!(
+                "UPDATE {}.{} SET orphaned = true WHERE signature = ?",
+                self.keyspace, self.transactions_table
+            ),
+            OrphanPolicy::Delete => format!(
+                "DELETE FROM {}.{} WHERE signature = ?",
+                self.keyspace, self.transactions_table
+            ),
+        };
+        let orphan_change = match policy {
+            OrphanPolicy::Mark => format!(
+                "UPDATE {}.{} SET orphaned = true WHERE owner = ? AND slot = ? AND signature = ? AND mint = ?",
+                self.keyspace, self.token_balance_changes_table
+            ),
+            OrphanPolicy::Delete => format!(
+                "DELETE FROM {}.{} WHERE owner = ? AND slot = ? AND signature = ? AND mint = ?",
+                self.keyspace, self.token_balance_changes_table
+            ),
+        };
+        for (signature, changes_json) in &signatures {
+            self.session
+                .query_unpaged(orphan_transaction.as_str(), (signature,))
+                .await?;
+
+            let changes: Vec<TokenBalanceChange> = match changes_json {
+                Some(changes_json) => serde_json::from_str(changes_json)?,
+                None => Vec::new(),
+            };
+            for change in &changes {
+                self.session
+                    .query_unpaged(
+                        orphan_change.as_str(),
+                        (&change.owner, slot, signature, &change.mint),
+                    )
+                    .await?;
+            }
+        }
+
+        let select_status_signatures = format!(
+            "SELECT signature FROM {}.{} WHERE slot = ?",
+            self.keyspace, self.transaction_statuses_table
+        );
+        let status_signatures = self
+            .session
+            .query_unpaged(select_status_signatures, (slot,))
+            .await?
+            .rows_typed::<(String,)>()?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let orphan_status = match policy {
+            OrphanPolicy::Mark => format!(
+                "UPDATE {}.{} SET orphaned = true WHERE signature = ?",
+                self.keyspace, self.transaction_statuses_table
+            ),
+            OrphanPolicy::Delete => format!(
+                "DELETE FROM {}.{} WHERE signature = ?",
+                self.keyspace, self.transaction_statuses_table
+            ),
+        };
+        for (signature,) in &status_signatures {
+            self.session
+                .query_unpaged(orphan_status.as_str(), (signature,))
+                .await?;
+        }
+
+        // Paged: a busy slot writes many accounts.
+        let select_pubkeys = Query::new(format!(
+            "SELECT pubkey FROM {}.{} WHERE slot = ?",
+            self.keyspace, self.account_slots_table
+        ))
+        .with_page_size(ORPHAN_PAGE_SIZE);
+        let mut pubkeys = self
+            .session
+            .query_iter(select_pubkeys, (slot,))
+            .await?
+            .into_typed::<(String,)>();
+        let mut orphaned_accounts = 0;
+        while let Some(row) = pubkeys.next().await {
+            let (pubkey,) = row?;
+            self.orphan_account_writes(&pubkey, slot, policy).await?;
+            orphaned_accounts += 1;
+        }
+
+        let orphan_slot = format!(
+            "UPDATE {}.{} SET orphaned = true WHERE slot = ?",
+            self.keyspace, self.slots_table
+        );
+        self.session.query_unpaged(orphan_slot, (slot,)).await?;
+
+        let orphan_block = match policy {
+            OrphanPolicy::Mark => format!(
+                "UPDATE {}.{} SET orphaned = true WHERE slot = ?",
+                self.keyspace, self.blocks_table
+            ),
+            OrphanPolicy::Delete => format!(
+                "DELETE FROM {}.{} WHERE slot = ?",
+                self.keyspace, self.blocks_table
+            ),
+        };
+        self.session.query_unpaged(orphan_block, (slot,)).await?;
+
+        info!(
+            "Orphaned slot {}: {:?} {} transactions, {} transaction statuses and {} accounts",
+            slot,
+            policy,
+            signatures.len(),
+            status_signatures.len(),
+            orphaned_accounts
+        );
+
+        Ok(())
+    }
+
+    /// Marks or deletes the writes of `pubkey` in `slot`. If the latest-state row still
+    /// holds one of them, it is rewritten from the newest write that is not orphaned; an
+    /// account with no such write is marked or deleted itself.
+    async fn orphan_account_writes(
+        &self,
+        pubkey: &str,
+        slot: i64,
+        policy: OrphanPolicy,
+    ) -> Result<(), Box<dyn Error>> {
+        match policy {
+            OrphanPolicy::Mark => {
+                let select_versions = format!(
+                    "SELECT write_version FROM {}.{} WHERE pubkey = ? AND slot = ?",
+                    self.keyspace, self.account_writes_table
+                );
+                let versions = self
+                    .session
+                    .query_unpaged(select_versions, (pubkey, slot))
+                    .await?
+                    .rows_typed::<(i64,)>()?
+                    .collect::<Result<Vec<_>, _>>()?;
+                let orphan_write = format!(
+                    "UPDATE {}.{} SET orphaned = true WHERE pubkey = ? AND slot = ? AND write_version = ?",
+                    self.keyspace, self.account_writes_table
+                );
+                for (write_version,) in versions {
+                    self.session
+                        .query_unpaged(orphan_write.as_str(), (pubkey, slot, write_version))
+                        .await?;
+                }
+            }
+            OrphanPolicy::Delete => {
+                let delete_writes = format!(
+                    "DELETE FROM {}.{} WHERE pubkey = ? AND slot = ?",
+                    self.keyspace, self.account_writes_table
+                );
+                self.session
+                    .query_unpaged(delete_writes, (pubkey, slot))
+                    .await?;
+            }
+        }
+
+        let select_current = format!(
+            "SELECT slot FROM {}.{} WHERE pubkey = ?",
+            self.keyspace, self.accounts_table
+        );
+        let current = self
+            .session
+            .query_unpaged(select_current, (pubkey,))
+            .await?
+            .maybe_first_row_typed::<(Option<i64>,)>()?;
+        if current != Some((Some(slot),)) {
+            // A later write already replaced it.
+            return Ok(());
+        }
+
+        // Filtering stays within the account's partition.
+        let select_previous = format!(
+            "SELECT pubkey, lamports, owner, executable, rent_epoch, data, write_version, slot, txn_signature, timestamp_ms, ingested_at_ms FROM {}.{} WHERE pubkey = ? AND orphaned = false LIMIT 1 ALLOW FILTERING",
+            self.keyspace, self.account_writes_table
+        );
+        let previous = self
+            .session
+            .query_unpaged(select_previous, (pubkey,))
+            .await?
+            .maybe_first_row_typed::<ScyllaAccount>()?;
+        match (previous, policy) {
+            (Some(previous), _) => self.upsert_account(&previous).await?,
+            (None, OrphanPolicy::Mark) => {
+                let orphan_account = format!(
+                    "UPDATE {}.{} SET orphaned = true WHERE pubkey = ?",
+                    self.keyspace, self.accounts_table
+                );
+                self.session
+                    .query_unpaged(orphan_account, (pubkey,))
+                    .await?;
+            }
+            (None, OrphanPolicy::Delete) => {
+                let delete_account = format!(
+                    "DELETE FROM {}.{} WHERE pubkey = ?",
+                    self.keyspace, self.accounts_table
+                );
+                self.session
+                    .query_unpaged(delete_account, (pubkey,))
+                    .await?;
+            }
+        }
         Ok(())
     }
 
@@ -227,7 +893,10 @@ impl ScyllaWriter {
         transaction: SolanaTransaction,
     ) -> Result<(), Box<dyn Error>> {
         let scylla_transaction = transaction.to_scylla()?;
+        let token_balance_changes: Vec<ScyllaTokenBalanceChange> = transaction.to_scylla()?;
         self.transaction_batch.push(scylla_transaction);
+        self.token_balance_change_batch
+            .extend(token_balance_changes);
         if self.transaction_batch.len() >= self.batch_size {
             self.flush_transaction_batch().await?;
         }
@@ -254,12 +923,82 @@ impl ScyllaWriter {
         Ok(())
     }
 
+    pub async fn add_slot(&mut self, slot: SlotUpdate) -> Result<(), Box<dyn Error>> {
+        let scylla_slot = slot.to_scylla()?;
+        self.slot_batch.push(scylla_slot);
+        if self.slot_batch.len() >= self.batch_size {
+            self.flush_slot_batch().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn add_slots(&mut self, slots: Vec<SlotUpdate>) -> Result<(), Box<dyn Error>> {
+        for slot in slots {
+            self.add_slot(slot).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn add_transaction_statuses(
+        &mut self,
+        statuses: Vec<TransactionStatus>,
+    ) -> Result<(), Box<dyn Error>> {
+        for status in statuses {
+            self.transaction_status_batch.push(status.to_scylla()?);
+            if self.transaction_status_batch.len() >= self.batch_size {
+                self.flush_transaction_status_batch().await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn add_block(&mut self, block: SolanaBlock) -> Result<(), Box<dyn Error>> {
+        let scylla_block = block.to_scylla()?;
+        self.block_batch.push(scylla_block);
+        if self.block_batch.len() >= self.batch_size {
+            self.flush_block_batch().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn add_blocks(&mut self, blocks: Vec<SolanaBlock>) -> Result<(), Box<dyn Error>> {
+        for block in blocks {
+            self.add_block(block).await?;
+        }
+        Ok(())
+    }
+
+    /// Runs a trivial query to find out whether the cluster answers again.
+    pub async fn check_health(&self) -> Result<(), Box<dyn Error>> {
+        self.session
+            .query_unpaged("SELECT release_version FROM system.local", &[])
+            .await?;
+        Ok(())
+    }
+
+    /// Drops rows waiting in the batches, e.g. after a failed flush left them there.
+    pub fn discard_batches(&mut self) {
+        self.account_batch.clear();
+        self.transaction_batch.clear();
+        self.token_balance_change_batch.clear();
+        self.slot_batch.clear();
+        self.block_batch.clear();
+        self.transaction_status_batch.clear();
+    }
+
     pub async fn flush_all_batches(&mut self) -> Result<(), Box<dyn Error>> {
         self.flush_account_batch().await?;
         self.flush_transaction_batch().await?;
+        self.flush_slot_batch().await?;
+        self.flush_block_batch().await?;
+        self.flush_transaction_status_batch().await?;
         Ok(())
     }
+}
 
+// Read helpers are not wired into the processor yet; keep them available for tooling.
+#[allow(dead_code)]
+impl ScyllaWriter {
     // ---------------------------
     // Queries - Accounts
     // ---------------------------
@@ -1,29 +1,50 @@
 use chrono::DateTime;
 
+use scylla::SerializeRow;
+use scylla::frame::value::CqlVarint;
 use scylla::macros::FromRow;
 use serde::{Deserialize, Serialize};
-use yellowstone_gRPC::types::{SolanaAccount, SolanaTransaction};
+use yellowstone_gRPC::types::{
+    SlotUpdate, SolanaAccount, SolanaBlock, SolanaTransaction, TransactionStatus,
+};
 
 /// ScyllaDB-compatible transaction struct that matches the schema exactly
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, SerializeRow)]
 pub struct ScyllaTransaction {
     pub signature: String,
     pub slot: i64,
     pub is_vote: bool,
     pub tx_index: i64,
     pub success: bool,
+    pub error_kind: Option<String>,
+    pub error_custom_code: Option<i64>,
+    pub error_json: Option<String>,
     pub fee: i64,
+    pub priority_fee: Option<i64>,
+    pub base_fee: Option<i64>,
     pub compute_units_consumed: i64,
+    pub cost_units: Option<i64>,
+    pub compute_budget_json: String,
     pub instructions_json: String,
+    pub inner_instructions_json: String,
     pub account_keys_json: String,
+    pub is_writable_json: String,
+    pub is_signer_json: String,
+    pub version: Option<i32>,
     pub log_messages_json: String,
     pub pre_balances_json: String,
     pub post_balances_json: String,
+    pub pre_token_balances_json: String,
+    pub post_token_balances_json: String,
+    pub token_balance_changes_json: String,
+    pub rewards_json: String,
+    pub return_data_json: Option<String>,
     pub timestamp_ms: i64,
+    pub ingested_at_ms: i64,
 }
 
 /// ScyllaDB-compatible account struct that matches the schema exactly
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, SerializeRow)]
 pub struct ScyllaAccount {
     pub pubkey: String,
     pub lamports: i64,
@@ -35,6 +56,65 @@ pub struct ScyllaAccount {
     pub slot: i64,
     pub txn_signature: String,
     pub timestamp_ms: i64,
+    pub ingested_at_ms: i64,
+}
+
+/// One `(owner, mint)` token balance change of a transaction
+#[derive(Debug, Clone, SerializeRow)]
+pub struct ScyllaTokenBalanceChange {
+    pub owner: String,
+    pub mint: String,
+    pub slot: i64,
+    pub signature: String,
+    pub amount_delta: CqlVarint,
+    pub ui_amount_delta: f64,
+    pub decimals: i32,
+    pub timestamp_ms: i64,
+}
+
+/// ScyllaDB-compatible slot status update; `timestamp_ms` lands in the column for its status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScyllaSlot {
+    pub slot: i64,
+    pub parent: Option<i64>,
+    pub status: String,
+    /// [`SlotStatus::rank`] of `status`
+    pub status_rank: i64,
+    pub dead_error: Option<String>,
+    pub timestamp_ms: i64,
+}
+
+/// ScyllaDB-compatible transaction status, bound to the insert by column name
+#[derive(Debug, Clone, SerializeRow)]
+pub struct ScyllaTransactionStatus {
+    pub signature: String,
+    pub slot: i64,
+    pub tx_index: i64,
+    pub is_vote: bool,
+    pub success: bool,
+    pub error_kind: Option<String>,
+    pub error_custom_code: Option<i64>,
+    pub error_json: Option<String>,
+    pub timestamp_ms: i64,
+    pub ingested_at_ms: i64,
+}
+
+/// ScyllaDB-compatible block; `entries_json` and `updated_account_count` are only known
+/// for some block updates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScyllaBlock {
+    pub slot: i64,
+    pub blockhash: String,
+    pub parent_slot: i64,
+    pub parent_blockhash: String,
+    pub block_height: Option<i64>,
+    pub block_time_ms: Option<i64>,
+    pub executed_transaction_count: i64,
+    pub entries_count: i64,
+    pub updated_account_count: Option<i64>,
+    pub rewards_json: String,
+    pub entries_json: Option<String>,
+    pub ingested_at_ms: i64,
 }
 
 /// Trait for converting domain structs to ScyllaDB-compatible structs
@@ -45,10 +125,16 @@ pub trait ToScyllaDb<T> {
 impl ToScyllaDb<ScyllaTransaction> for SolanaTransaction {
     fn to_scylla(&self) -> Result<ScyllaTransaction, Box<dyn std::error::Error>> {
         let instructions_json = serde_json::to_string(&self.instructions)?;
+        let inner_instructions_json = serde_json::to_string(&self.inner_instructions)?;
         let account_keys_json = serde_json::to_string(&self.account_keys)?;
+        let is_writable_json = serde_json::to_string(&self.is_writable)?;
+        let is_signer_json = serde_json::to_string(&self.is_signer)?;
         let log_messages_json = serde_json::to_string(&self.log_messages)?;
         let pre_balances_json = serde_json::to_string(&self.pre_balances)?;
         let post_balances_json = serde_json::to_string(&self.post_balances)?;
+        let pre_token_balances_json = serde_json::to_string(&self.pre_token_balances)?;
+        let post_token_balances_json = serde_json::to_string(&self.post_token_balances)?;
+        let token_balance_changes_json = serde_json::to_string(&self.token_balance_changes)?;
 
         Ok(ScyllaTransaction {
             signature: self.signature.clone(),
@@ -56,18 +142,62 @@ impl ToScyllaDb<ScyllaTransaction> for SolanaTransaction {
             is_vote: self.is_vote,
             tx_index: self.index as i64,
             success: self.success,
+            error_kind: self.error.as_ref().map(|error| error.kind.clone()),
+            error_custom_code: self
+                .error
+                .as_ref()
+                .and_then(|error| error.custom_code)
+                .map(i64::from),
+            error_json: self.error.as_ref().map(serde_json::to_string).transpose()?,
             fee: self.fee.unwrap_or(0) as i64,
+            priority_fee: self.priority_fee.map(|fee| fee as i64),
+            base_fee: self.base_fee.map(|fee| fee as i64),
             compute_units_consumed: self.compute_units_consumed.unwrap_or(0) as i64,
+            cost_units: self.cost_units.map(|units| units as i64),
+            compute_budget_json: serde_json::to_string(&self.compute_budget)?,
             instructions_json,
+            inner_instructions_json,
             account_keys_json,
+            is_writable_json,
+            is_signer_json,
+            version: self.version.map(i32::from),
             log_messages_json,
             pre_balances_json,
             post_balances_json,
+            pre_token_balances_json,
+            post_token_balances_json,
+            token_balance_changes_json,
+            rewards_json: serde_json::to_string(&self.rewards)?,
+            return_data_json: self
+                .return_data
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
             timestamp_ms: self.timestamp.timestamp_millis(),
+            ingested_at_ms: self.ingested_at.timestamp_millis(),
         })
     }
 }
 
+impl ToScyllaDb<Vec<ScyllaTokenBalanceChange>> for SolanaTransaction {
+    fn to_scylla(&self) -> Result<Vec<ScyllaTokenBalanceChange>, Box<dyn std::error::Error>> {
+        Ok(self
+            .token_balance_changes
+            .iter()
+            .map(|change| ScyllaTokenBalanceChange {
+                owner: change.owner.clone(),
+                mint: change.mint.clone(),
+                slot: self.slot as i64,
+                signature: self.signature.clone(),
+                amount_delta: CqlVarint::from_signed_bytes_be_slice(&change.delta.to_be_bytes()),
+                ui_amount_delta: change.ui_delta,
+                decimals: change.decimals as i32,
+                timestamp_ms: self.timestamp.timestamp_millis(),
+            })
+            .collect())
+    }
+}
+
 impl ToScyllaDb<ScyllaAccount> for SolanaAccount {
     fn to_scylla(&self) -> Result<ScyllaAccount, Box<dyn std::error::Error>> {
         Ok(ScyllaAccount {
@@ -81,11 +211,70 @@ impl ToScyllaDb<ScyllaAccount> for SolanaAccount {
             slot: self.slot as i64,
             txn_signature: self.txn_signature.clone().unwrap_or_default(),
             timestamp_ms: self.timestamp.timestamp_millis(),
+            ingested_at_ms: self.ingested_at.timestamp_millis(),
+        })
+    }
+}
+
+impl ToScyllaDb<ScyllaSlot> for SlotUpdate {
+    fn to_scylla(&self) -> Result<ScyllaSlot, Box<dyn std::error::Error>> {
+        Ok(ScyllaSlot {
+            slot: self.slot as i64,
+            parent: self.parent.map(|parent| parent as i64),
+            status: self.status.as_str().to_string(),
+            status_rank: self.status.rank() as i64,
+            dead_error: self.dead_error.clone(),
+            timestamp_ms: self.timestamp.timestamp_millis(),
+        })
+    }
+}
+
+impl ToScyllaDb<ScyllaTransactionStatus> for TransactionStatus {
+    fn to_scylla(&self) -> Result<ScyllaTransactionStatus, Box<dyn std::error::Error>> {
+        Ok(ScyllaTransactionStatus {
+            signature: self.signature.clone(),
+            slot: self.slot as i64,
+            tx_index: self.index as i64,
+            is_vote: self.is_vote,
+            success: self.success,
+            error_kind: self.error.as_ref().map(|error| error.kind.clone()),
+            error_custom_code: self
+                .error
+                .as_ref()
+                .and_then(|error| error.custom_code)
+                .map(i64::from),
+            error_json: self.error.as_ref().map(serde_json::to_string).transpose()?,
+            timestamp_ms: self.timestamp.timestamp_millis(),
+            ingested_at_ms: self.ingested_at.timestamp_millis(),
+        })
+    }
+}
+
+impl ToScyllaDb<ScyllaBlock> for SolanaBlock {
+    fn to_scylla(&self) -> Result<ScyllaBlock, Box<dyn std::error::Error>> {
+        Ok(ScyllaBlock {
+            slot: self.slot as i64,
+            blockhash: self.blockhash.clone(),
+            parent_slot: self.parent_slot as i64,
+            parent_blockhash: self.parent_blockhash.clone(),
+            block_height: self.block_height.map(|height| height as i64),
+            block_time_ms: self.block_time.map(|time| time.timestamp_millis()),
+            executed_transaction_count: self.executed_transaction_count as i64,
+            entries_count: self.entries_count as i64,
+            updated_account_count: self.updated_account_count.map(|count| count as i64),
+            rewards_json: serde_json::to_string(&self.rewards)?,
+            entries_json: self
+                .entries
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+            ingested_at_ms: self.ingested_at.timestamp_millis(),
         })
     }
 }
 
 /// Helper trait for converting from ScyllaDB structs back to domain structs
+#[allow(dead_code)]
 pub trait FromScyllaDb<T> {
     fn from_scylla(scylla_data: T) -> Result<Self, Box<dyn std::error::Error>>
     where
@@ -95,13 +284,21 @@ pub trait FromScyllaDb<T> {
 impl FromScyllaDb<ScyllaTransaction> for SolanaTransaction {
     fn from_scylla(scylla_tx: ScyllaTransaction) -> Result<Self, Box<dyn std::error::Error>> {
         let instructions = serde_json::from_str(&scylla_tx.instructions_json)?;
+        let inner_instructions = serde_json::from_str(&scylla_tx.inner_instructions_json)?;
         let account_keys = serde_json::from_str(&scylla_tx.account_keys_json)?;
+        let is_writable = serde_json::from_str(&scylla_tx.is_writable_json)?;
+        let is_signer = serde_json::from_str(&scylla_tx.is_signer_json)?;
         let log_messages = serde_json::from_str(&scylla_tx.log_messages_json)?;
         let pre_balances = serde_json::from_str(&scylla_tx.pre_balances_json)?;
         let post_balances = serde_json::from_str(&scylla_tx.post_balances_json)?;
+        let pre_token_balances = serde_json::from_str(&scylla_tx.pre_token_balances_json)?;
+        let post_token_balances = serde_json::from_str(&scylla_tx.post_token_balances_json)?;
+        let token_balance_changes = serde_json::from_str(&scylla_tx.token_balance_changes_json)?;
 
         let timestamp =
             DateTime::from_timestamp_millis(scylla_tx.timestamp_ms).ok_or("Invalid timestamp")?;
+        let ingested_at = DateTime::from_timestamp_millis(scylla_tx.ingested_at_ms)
+            .ok_or("Invalid ingested_at")?;
 
         Ok(SolanaTransaction {
             signature: scylla_tx.signature,
@@ -109,22 +306,45 @@ impl FromScyllaDb<ScyllaTransaction> for SolanaTransaction {
             is_vote: scylla_tx.is_vote,
             index: scylla_tx.tx_index as u64,
             success: scylla_tx.success,
+            error: scylla_tx
+                .error_json
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
             fee: if scylla_tx.fee == 0 {
                 None
             } else {
                 Some(scylla_tx.fee as u64)
             },
+            priority_fee: scylla_tx.priority_fee.map(|fee| fee as u64),
+            base_fee: scylla_tx.base_fee.map(|fee| fee as u64),
             compute_units_consumed: if scylla_tx.compute_units_consumed == 0 {
                 None
             } else {
                 Some(scylla_tx.compute_units_consumed as u64)
             },
+            cost_units: scylla_tx.cost_units.map(|units| units as u64),
+            compute_budget: serde_json::from_str(&scylla_tx.compute_budget_json)?,
             instructions,
+            inner_instructions,
             account_keys,
+            is_writable,
+            is_signer,
+            version: scylla_tx.version.map(|version| version as u8),
             log_messages,
             pre_balances,
             post_balances,
+            pre_token_balances,
+            post_token_balances,
+            token_balance_changes,
+            rewards: serde_json::from_str(&scylla_tx.rewards_json)?,
+            return_data: scylla_tx
+                .return_data_json
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
             timestamp,
+            ingested_at,
         })
     }
 }
@@ -133,6 +353,8 @@ impl FromScyllaDb<ScyllaAccount> for SolanaAccount {
     fn from_scylla(scylla_acc: ScyllaAccount) -> Result<Self, Box<dyn std::error::Error>> {
         let timestamp =
             DateTime::from_timestamp_millis(scylla_acc.timestamp_ms).ok_or("Invalid timestamp")?;
+        let ingested_at = DateTime::from_timestamp_millis(scylla_acc.ingested_at_ms)
+            .ok_or("Invalid ingested_at")?;
 
         Ok(SolanaAccount {
             pubkey: scylla_acc.pubkey,
@@ -149,6 +371,7 @@ impl FromScyllaDb<ScyllaAccount> for SolanaAccount {
                 Some(scylla_acc.txn_signature)
             },
             timestamp,
+            ingested_at,
         })
     }
 }
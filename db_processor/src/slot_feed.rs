@@ -0,0 +1,115 @@
+use redis::{
+    Client, Commands, RedisResult,
+    streams::{StreamMaxlen, StreamReadOptions, StreamReadReply},
+};
+use tracing::warn;
+use yellowstone_gRPC::codec::{CODEC_FIELD, Codec, PAYLOAD_FIELD};
+use yellowstone_gRPC::schema::Decoded;
+use yellowstone_gRPC::types::{IndexEvent, SlotUpdate};
+
+/// Slot updates kept on the feed. A replica that starts reads all of them, which covers
+/// the slots the fork tracker remembers several times over.
+pub const SLOT_FEED_MAX_LEN: usize = 20_000;
+
+/// Entries read per round trip.
+const READ_BATCH: usize = 1_000;
+
+/// Name of the slot feed of `stream_name`.
+pub fn stream_name(stream_name: &str) -> String {
+    format!("{}:slots", stream_name)
+}
+
+/// Hands every slot update to every replica. Replicas in one consumer group each read
+/// only part of the main stream, but the fork tracker and the finality gate need the
+/// whole slot history. So each replica adds the slot updates it consumed to this feed,
+/// and reads the feed in full, without a consumer group.
+pub struct SlotFeed {
+    client: Client,
+    stream_name: String,
+    codec: Codec,
+    /// ID of the last entry read; starts at the oldest entry kept.
+    last_id: String,
+}
+
+impl SlotFeed {
+    pub fn new(redis_url: &str, stream_name: &str) -> RedisResult<Self> {
+        Ok(Self {
+            client: Client::open(redis_url)?,
+            stream_name: stream_name.to_string(),
+            codec: Codec::default(),
+            last_id: "0".to_string(),
+        })
+    }
+
+    pub fn share(&self, slots: &[&SlotUpdate]) -> RedisResult<()> {
+        if slots.is_empty() {
+            return Ok(());
+        }
+        let mut pipe = redis::pipe();
+        for &slot in slots {
+            let payload = self
+                .codec
+                .encode(&IndexEvent::Slot(slot.clone()))
+                .map_err(|e| {
+                    redis::RedisError::from((
+                        redis::ErrorKind::TypeError,
+                        "Failed to encode slot update",
+                        e.to_string(),
+                    ))
+                })?;
+            let fields = [
+                (PAYLOAD_FIELD, payload),
+                (CODEC_FIELD, self.codec.header().into_bytes()),
+            ];
+            pipe.xadd_maxlen(
+                &self.stream_name,
+                StreamMaxlen::Approx(SLOT_FEED_MAX_LEN),
+                "*",
+                &fields,
+            )
+            .ignore();
+        }
+        let mut conn = self.client.get_connection()?;
+        pipe.query::<()>(&mut conn)
+    }
+
+    /// Slot updates shared since the last read, from every replica.
+    pub fn read(&mut self) -> RedisResult<Vec<SlotUpdate>> {
+        let mut conn = self.client.get_connection()?;
+        let mut slots = Vec::new();
+        loop {
+            let reply: StreamReadReply = conn.xread_options(
+                &[&self.stream_name],
+                &[&self.last_id],
+                &StreamReadOptions::default().count(READ_BATCH),
+            )?;
+            let entries: Vec<_> = reply
+                .keys
+                .into_iter()
+                .flat_map(|stream| stream.ids)
+                .collect();
+            if entries.is_empty() {
+                return Ok(slots);
+            }
+
+            for entry in entries {
+                let payload: Vec<u8> = entry.get(PAYLOAD_FIELD).unwrap_or_default();
+                let header: Option<String> = entry.get(CODEC_FIELD);
+                let decoded =
+                    Codec::from_header(header.as_deref()).and_then(|codec| codec.decode(&payload));
+                match decoded {
+                    Ok(Decoded::Event(IndexEvent::Slot(slot))) => slots.push(slot),
+                    Ok(_) => warn!(
+                        "Skipping {} on {}: not a slot update",
+                        entry.id, self.stream_name
+                    ),
+                    Err(e) => warn!(
+                        "Skipping {} on {}: failed to decode: {:#}",
+                        entry.id, self.stream_name, e
+                    ),
+                }
+                self.last_id = entry.id;
+            }
+        }
+    }
+}
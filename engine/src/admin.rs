@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+use yellowstone_gRPC::control::{ControlMessage, SubscriptionCommand};
+
+/// Serves the line-based admin protocol on a Unix socket, forwarding each command to
+/// the subscription loop and writing back `ok <message>` or `error <message>`.
+///
+/// ```text
+/// $ echo "add-program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc" | nc -U /tmp/engine.sock
+/// ok add-program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc: applied
+/// ```
+pub fn spawn_admin_socket(path: &str, commands: mpsc::Sender<ControlMessage>) -> Result<()> {
+    if Path::new(path).exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale admin socket {}", path))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind admin socket {}", path))?;
+    info!("Admin socket listening on {}", path);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((connection, _)) => {
+                    let commands = commands.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(connection, commands).await {
+                            error!("Admin connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to accept admin connection: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    connection: UnixStream,
+    commands: mpsc::Sender<ControlMessage>,
+) -> Result<()> {
+    let (reader, mut writer) = connection.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match SubscriptionCommand::parse(&line) {
+            Ok(command) => {
+                let (reply, outcome) = oneshot::channel();
+                commands
+                    .send(ControlMessage { command, reply })
+                    .await
+                    .context("Subscription loop is not running")?;
+                match outcome.await {
+                    Ok(Ok(message)) => format!("ok {}\n", message),
+                    Ok(Err(message)) => format!("error {}\n", message),
+                    Err(_) => "error subscription loop dropped the command\n".to_string(),
+                }
+            }
+            Err(e) => format!("error {:#}\n", e),
+        };
+
+        writer.write_all(response.as_bytes()).await?;
+    }
+
+    Ok(())
+}
@@ -1,18 +1,276 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use std::env;
+use std::time::Duration;
+use yellowstone_gRPC::backpressure::{BackpressurePolicy, EventClass};
+use yellowstone_gRPC::codec::{self, Codec};
+use yellowstone_gRPC::keepalive::KeepalivePolicy;
+use yellowstone_gRPC::publisher::PublishPolicy;
+use yellowstone_gRPC::reconnect::ReconnectPolicy;
+use yellowstone_gRPC::retention::{RetentionPolicy, StreamTrim};
+use yellowstone_gRPC::session::{EndpointConfig, StreamMode};
+use yellowstone_gRPC::subscription_spec::SubscriptionSpec;
+use yellowstone_gRPC::subscriptions::{Subscriptions, TransactionMode};
+use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeRequestFilterBlocks};
+
+const FULL_BLOCKS_FILTER: &str = "full_blocks";
 
 pub struct Config {
-    pub yellowstone_endpoint: String,
-    pub yellowstone_token: Option<String>,
-    pub redis_url: String
+    pub yellowstone_endpoints: Vec<EndpointConfig>,
+    pub stream_mode: StreamMode,
+    pub redis_url: String,
+    pub reconnect: ReconnectPolicy,
+    pub keepalive: KeepalivePolicy,
+    pub publish: PublishPolicy,
+    pub retention: RetentionPolicy,
+    pub backpressure: BackpressurePolicy,
+    pub subscription: SubscribeRequest,
+    pub transaction_mode: TransactionMode,
+    pub admin_socket: Option<String>,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
+        let defaults = ReconnectPolicy::default();
+        let reconnect = ReconnectPolicy {
+            initial_backoff: env_duration_ms("RECONNECT_INITIAL_BACKOFF_MS")?
+                .unwrap_or(defaults.initial_backoff),
+            max_backoff: env_duration_ms("RECONNECT_MAX_BACKOFF_MS")?
+                .unwrap_or(defaults.max_backoff),
+            multiplier: defaults.multiplier,
+            max_attempts: env_parse("RECONNECT_MAX_ATTEMPTS")?,
+        };
+
+        let keepalive_defaults = KeepalivePolicy::default();
+        let keepalive = KeepalivePolicy {
+            interval: env_duration_ms("PING_INTERVAL_MS")?.unwrap_or(keepalive_defaults.interval),
+            timeout: env_duration_ms("PING_TIMEOUT_MS")?.unwrap_or(keepalive_defaults.timeout),
+        };
+
+        let publish_defaults = PublishPolicy::default();
+        let publish = PublishPolicy {
+            queue_capacity: env_parse("PUBLISH_QUEUE_CAPACITY")?
+                .unwrap_or(publish_defaults.queue_capacity),
+            max_batch: env_parse("PUBLISH_MAX_BATCH")?.unwrap_or(publish_defaults.max_batch),
+            metrics_interval: env_duration_ms("PUBLISH_METRICS_INTERVAL_MS")?
+                .unwrap_or(publish_defaults.metrics_interval),
+            codec: load_codec()?,
+        };
+        if publish.queue_capacity == 0
+            || publish.max_batch == 0
+            || publish.metrics_interval.is_zero()
+        {
+            bail!(
+                "PUBLISH_QUEUE_CAPACITY, PUBLISH_MAX_BATCH and PUBLISH_METRICS_INTERVAL_MS must be greater than zero"
+            );
+        }
+
+        let transaction_mode: TransactionMode = env_parse("TRANSACTION_MODE")?.unwrap_or_default();
+        let subscription = apply_block_toggle(apply_failed_toggle(load_subscription()?)?)?;
+        if transaction_mode == TransactionMode::Status && !subscription.blocks.is_empty() {
+            bail!("TRANSACTION_MODE=status cannot be combined with block subscriptions");
+        }
+
         Ok(Self {
-            yellowstone_endpoint: env::var("YELLOWSTONE_ENDPOINT")?,
-            yellowstone_token: env::var("YELLOWSTONE_TOKEN").ok(),
+            yellowstone_endpoints: load_endpoints()?,
+            stream_mode: env_parse("YELLOWSTONE_MODE")?.unwrap_or_default(),
             redis_url: env::var("REDIS_URL")?,
+            reconnect,
+            keepalive,
+            publish,
+            retention: load_retention()?,
+            backpressure: load_backpressure()?,
+            subscription,
+            transaction_mode,
+            admin_socket: env::var("ADMIN_SOCKET").ok(),
         })
     }
 }
+
+/// Reads `YELLOWSTONE_ENDPOINTS` (comma-separated) with `YELLOWSTONE_TOKENS` aligned by
+/// position, or the single `YELLOWSTONE_ENDPOINT`/`YELLOWSTONE_TOKEN` pair.
+fn load_endpoints() -> Result<Vec<EndpointConfig>> {
+    let Ok(urls) = env::var("YELLOWSTONE_ENDPOINTS") else {
+        return Ok(vec![EndpointConfig {
+            url: env::var("YELLOWSTONE_ENDPOINT")
+                .context("YELLOWSTONE_ENDPOINT or YELLOWSTONE_ENDPOINTS must be set")?,
+            token: env::var("YELLOWSTONE_TOKEN").ok(),
+        }]);
+    };
+
+    let urls: Vec<String> = urls
+        .split(',')
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect();
+    if urls.is_empty() {
+        bail!("YELLOWSTONE_ENDPOINTS does not contain any endpoint");
+    }
+
+    let tokens: Vec<Option<String>> = match env::var("YELLOWSTONE_TOKENS") {
+        Ok(tokens) => {
+            let tokens: Vec<Option<String>> = tokens
+                .split(',')
+                .map(|token| Some(token.trim().to_string()).filter(|token| !token.is_empty()))
+                .collect();
+            if tokens.len() != urls.len() {
+                bail!(
+                    "YELLOWSTONE_TOKENS has {} entries but YELLOWSTONE_ENDPOINTS has {}",
+                    tokens.len(),
+                    urls.len()
+                );
+            }
+            tokens
+        }
+        Err(_) => vec![env::var("YELLOWSTONE_TOKEN").ok(); urls.len()],
+    };
+
+    Ok(urls
+        .into_iter()
+        .zip(tokens)
+        .map(|(url, token)| EndpointConfig { url, token })
+        .collect())
+}
+
+/// `STREAM_CODEC` picks the payload format; `STREAM_ZSTD_LEVEL` compresses payloads with
+/// zstd at that level.
+fn load_codec() -> Result<Codec> {
+    let zstd_level = env_parse::<i32>("STREAM_ZSTD_LEVEL")?;
+    if let Some(level) = zstd_level
+        && !codec::zstd_levels().contains(&level)
+    {
+        bail!(
+            "STREAM_ZSTD_LEVEL must be within {:?}",
+            codec::zstd_levels()
+        );
+    }
+    Ok(Codec {
+        format: env_parse("STREAM_CODEC")?.unwrap_or_default(),
+        zstd_level,
+        ..Codec::default()
+    })
+}
+
+/// `STREAM_MAXLEN` or `STREAM_MAX_AGE_MS` bound the stream; `STREAM_LAG_POLICY` says
+/// what happens when a consumer group falls behind that bound.
+fn load_retention() -> Result<RetentionPolicy> {
+    let defaults = RetentionPolicy::default();
+    let trim = match (
+        env_parse::<usize>("STREAM_MAXLEN")?,
+        env_duration_ms("STREAM_MAX_AGE_MS")?,
+    ) {
+        (Some(_), Some(_)) => bail!("Set only one of STREAM_MAXLEN and STREAM_MAX_AGE_MS"),
+        (Some(max_len), None) => StreamTrim::MaxLen(max_len),
+        (None, Some(max_age)) => StreamTrim::MaxAge(max_age),
+        (None, None) => StreamTrim::None,
+    };
+    let check_interval =
+        env_duration_ms("STREAM_LAG_CHECK_INTERVAL_MS")?.unwrap_or(defaults.check_interval);
+    if check_interval.is_zero() {
+        bail!("STREAM_LAG_CHECK_INTERVAL_MS must be greater than zero");
+    }
+
+    Ok(RetentionPolicy {
+        trim,
+        lag_policy: env_parse("STREAM_LAG_POLICY")?.unwrap_or(defaults.lag_policy),
+        spill_path: env::var("STREAM_SPILL_PATH")
+            .map(Into::into)
+            .unwrap_or(defaults.spill_path),
+        check_interval,
+    })
+}
+
+/// `BACKPRESSURE_SHED_BACKLOG` and `BACKPRESSURE_PAUSE_BACKLOG` are consumer group
+/// backlogs (undelivered plus pending entries); `BACKPRESSURE_SHED_EVENTS` lists the
+/// event classes dropped first.
+fn load_backpressure() -> Result<BackpressurePolicy> {
+    let shed_backlog = env_parse("BACKPRESSURE_SHED_BACKLOG")?;
+    let pause_backlog = env_parse("BACKPRESSURE_PAUSE_BACKLOG")?;
+    if let (Some(shed), Some(pause)) = (shed_backlog, pause_backlog)
+        && pause <= shed
+    {
+        bail!("BACKPRESSURE_PAUSE_BACKLOG must be greater than BACKPRESSURE_SHED_BACKLOG");
+    }
+
+    let shed_events = match env::var("BACKPRESSURE_SHED_EVENTS") {
+        Ok(classes) => classes
+            .split(',')
+            .map(str::trim)
+            .filter(|class| !class.is_empty())
+            .map(|class| {
+                class
+                    .parse()
+                    .context("Invalid value for BACKPRESSURE_SHED_EVENTS")
+            })
+            .collect::<Result<Vec<EventClass>>>()?,
+        Err(_) => vec![EventClass::Account],
+    };
+
+    Ok(BackpressurePolicy {
+        shed_backlog,
+        pause_backlog,
+        shed_events,
+    })
+}
+
+/// Builds the subscription from the TOML spec at `SUBSCRIPTION_CONFIG`, falling back to
+/// the built-in DeFi subscription when the variable is unset.
+fn load_subscription() -> Result<SubscribeRequest> {
+    match env::var("SUBSCRIPTION_CONFIG") {
+        Ok(path) => {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read subscription spec {}", path))?;
+            SubscriptionSpec::from_toml_str(&contents)
+                .and_then(|spec| spec.to_subscribe_request())
+                .with_context(|| format!("Invalid subscription spec {}", path))
+        }
+        Err(_) => Ok(Subscriptions::create_defi_subscription()),
+    }
+}
+
+/// `INCLUDE_FAILED_TRANSACTIONS` overrides the `failed` setting of every transaction
+/// filter: `true` streams failed transactions alongside successful ones (so their errors
+/// can be analysed), `false` drops them.
+fn apply_failed_toggle(mut subscription: SubscribeRequest) -> Result<SubscribeRequest> {
+    if let Some(include_failed) = env_parse::<bool>("INCLUDE_FAILED_TRANSACTIONS")? {
+        let failed = if include_failed { None } else { Some(false) };
+        for filter in subscription.transactions.values_mut() {
+            filter.failed = failed;
+        }
+    }
+    Ok(subscription)
+}
+
+/// `INGEST_BLOCKS=true` adds a full block subscription with transactions and entries, so
+/// every transaction of a block is indexed together with its block row and entry list.
+fn apply_block_toggle(mut subscription: SubscribeRequest) -> Result<SubscribeRequest> {
+    if env_parse::<bool>("INGEST_BLOCKS")?.unwrap_or(false) {
+        subscription.blocks.insert(
+            FULL_BLOCKS_FILTER.to_string(),
+            SubscribeRequestFilterBlocks {
+                account_include: vec![],
+                include_transactions: Some(true),
+                include_accounts: Some(false),
+                include_entries: Some(true),
+            },
+        );
+    }
+    Ok(subscription)
+}
+
+fn env_parse<T>(name: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(e) => bail!("Invalid value for {}: {} ({})", name, value, e),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_duration_ms(name: &str) -> Result<Option<Duration>> {
+    Ok(env_parse::<u64>(name)?.map(Duration::from_millis))
+}
@@ -1,11 +1,17 @@
+mod admin;
 mod config;
 
 use crate::config::Config;
 use anyhow::Result;
-use futures::SinkExt;
 use redis::Client;
-use tracing::info;
-use yellowstone_gRPC::{client::YellowstoneClient, subscriptions::Subscriptions};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use yellowstone_gRPC::client::{SubscriptionState, YellowstoneClient};
+use yellowstone_gRPC::codec::Format;
+use yellowstone_gRPC::publisher::Publisher;
+use yellowstone_gRPC::retention::StreamStatus;
+
+const STREAM_NAME: &str = "yellowstone_gRPC_streams";
 
 fn setup_logging() {
     tracing_subscriber::fmt()
@@ -30,23 +36,60 @@ async fn main() -> Result<()> {
     info!("Starting Solana Indexer Pipeline");
 
     let config = Config::from_env()?;
+    if config.publish.codec.format == Format::Bincode {
+        warn!(
+            "STREAM_CODEC=bincode: upgrade the engine and every db_processor together; bincode payloads of another schema version are dead-lettered"
+        );
+    }
 
-    let mut yellowstone_client = YellowstoneClient::create_yellowstone_client(
-        &config.yellowstone_endpoint,
-        config.yellowstone_token,
+    let redis_client = Client::open(config.redis_url)?;
+    let mut redis_connection = redis_client.get_multiplexed_async_connection().await?;
+    StreamStatus::fetch(&mut redis_connection, STREAM_NAME)
+        .await?
+        .log(STREAM_NAME, &config.retention.trim);
+    let (publisher, publisher_task) = Publisher::spawn(
+        redis_connection,
+        STREAM_NAME,
+        config.publish,
+        config.retention,
+        config.backpressure,
     )
     .await?;
 
-    let (mut subscriber_tx, subscribe_rx) =
-        YellowstoneClient::subscribe(&mut yellowstone_client).await?;
-    let defi_subscription_request = Subscriptions::create_defi_subscription();
+    info!(
+        "Streaming from {} endpoint(s) in {:?} mode",
+        config.yellowstone_endpoints.len(),
+        config.stream_mode
+    );
+    info!(
+        "Subscribing with {} account, {} transaction and {} slot filters in {:?} transaction mode",
+        config.subscription.accounts.len(),
+        config.subscription.transactions.len(),
+        config.subscription.slots.len(),
+        config.transaction_mode
+    );
+    let commands = match &config.admin_socket {
+        Some(path) => {
+            let (commands_tx, commands_rx) = mpsc::channel(16);
+            admin::spawn_admin_socket(path, commands_tx)?;
+            Some(commands_rx)
+        }
+        None => None,
+    };
 
-    subscriber_tx.send(defi_subscription_request).await?;
-
-    info!("Subscribed to defi transactions. Starting stream processing...");
-    let redis_client = Client::open(config.redis_url)?;
-    let mut redis_connection = redis_client.get_connection()?;
-    YellowstoneClient::handle_stream(subscribe_rx, &mut redis_connection, "yellowstone_gRPC_streams").await?;
+    let subscription = YellowstoneClient::run_with_reconnect(
+        config.yellowstone_endpoints,
+        config.stream_mode,
+        SubscriptionState::new(config.subscription, config.transaction_mode, commands),
+        &publisher,
+        config.reconnect,
+        config.keepalive,
+    );
 
-    Ok(())
+    // A failed pipeline stops the publisher; surface its error rather than the closed
+    // queue the subscription would report next.
+    tokio::select! {
+        result = subscription => result,
+        result = publisher_task => result?,
+    }
 }
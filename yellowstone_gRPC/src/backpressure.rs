@@ -0,0 +1,198 @@
+use crate::retention::StreamStatus;
+use crate::types::IndexEvent;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+/// A level is left once the backlog falls below this share of its threshold, so the
+/// engine does not flap around a threshold.
+pub const RESUME_RATIO: f64 = 0.5;
+
+/// Kinds of events on the stream, as named in `BACKPRESSURE_SHED_EVENTS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventClass {
+    Account,
+    Transaction,
+    TransactionStatus,
+    Block,
+    Slot,
+}
+
+impl EventClass {
+    pub fn of(event: &IndexEvent) -> Self {
+        match event {
+            IndexEvent::Account(_) => Self::Account,
+            IndexEvent::Transaction(_) => Self::Transaction,
+            IndexEvent::TransactionStatus(_) => Self::TransactionStatus,
+            IndexEvent::Block(_) => Self::Block,
+            IndexEvent::Slot(_) => Self::Slot,
+        }
+    }
+}
+
+impl FromStr for EventClass {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "account" => Ok(Self::Account),
+            "transaction" => Ok(Self::Transaction),
+            "transaction_status" => Ok(Self::TransactionStatus),
+            "block" => Ok(Self::Block),
+            "slot" => Ok(Self::Slot),
+            other => Err(anyhow!(
+                "unknown event class '{}' (expected account, transaction, transaction_status, block or slot)",
+                other
+            )),
+        }
+    }
+}
+
+/// How hard the engine is pushing back on the gRPC stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum BackpressureLevel {
+    #[default]
+    None,
+    /// Low-priority event classes are dropped before publishing.
+    Shed,
+    /// Updates are no longer read from the gRPC stream.
+    Paused,
+}
+
+/// Consumer group backlog (undelivered plus pending entries of the furthest-behind
+/// group) at which events are shed and at which reading from gRPC pauses.
+#[derive(Debug, Clone, Default)]
+pub struct BackpressurePolicy {
+    pub shed_backlog: Option<usize>,
+    pub pause_backlog: Option<usize>,
+    pub shed_events: Vec<EventClass>,
+}
+
+impl BackpressurePolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.shed_backlog.is_some() || self.pause_backlog.is_some()
+    }
+
+    /// The level for `status`, given the level currently applied.
+    pub fn level(&self, status: &StreamStatus, current: BackpressureLevel) -> BackpressureLevel {
+        let backlog = status
+            .groups
+            .iter()
+            .map(|group| group.lag.unwrap_or(0) + group.pending)
+            .max()
+            .unwrap_or(0);
+        let reached = |threshold: Option<usize>, level: BackpressureLevel| {
+            threshold.is_some_and(|threshold| {
+                let threshold = if current >= level {
+                    threshold as f64 * RESUME_RATIO
+                } else {
+                    threshold as f64
+                };
+                backlog as f64 >= threshold
+            })
+        };
+
+        if reached(self.pause_backlog, BackpressureLevel::Paused) {
+            BackpressureLevel::Paused
+        } else if reached(self.shed_backlog, BackpressureLevel::Shed) {
+            BackpressureLevel::Shed
+        } else {
+            BackpressureLevel::None
+        }
+    }
+
+    /// Whether `event` is dropped at `level`. Shedding continues while paused, so the
+    /// updates already read are thinned out too.
+    pub fn sheds(&self, level: BackpressureLevel, event: &IndexEvent) -> bool {
+        level >= BackpressureLevel::Shed && self.shed_events.contains(&EventClass::of(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retention::GroupLag;
+
+    fn status(groups: &[(Option<usize>, usize)]) -> StreamStatus {
+        StreamStatus {
+            length: 0,
+            groups: groups
+                .iter()
+                .enumerate()
+                .map(|(i, &(lag, pending))| GroupLag {
+                    name: format!("group-{}", i),
+                    lag,
+                    pending,
+                    oldest_needed_ms: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn policy() -> BackpressurePolicy {
+        BackpressurePolicy {
+            shed_backlog: Some(1_000),
+            pause_backlog: Some(4_000),
+            shed_events: vec![EventClass::Account],
+        }
+    }
+
+    #[test]
+    fn follows_the_furthest_behind_group() {
+        let policy = policy();
+        let level = |groups| policy.level(&status(groups), BackpressureLevel::None);
+        assert_eq!(level(&[]), BackpressureLevel::None);
+        assert_eq!(level(&[(Some(999), 0)]), BackpressureLevel::None);
+        assert_eq!(level(&[(Some(600), 400)]), BackpressureLevel::Shed);
+        assert_eq!(
+            level(&[(Some(10), 0), (Some(3_000), 1_000)]),
+            BackpressureLevel::Paused
+        );
+        // An unknown lag counts as nothing undelivered.
+        assert_eq!(level(&[(None, 1_000)]), BackpressureLevel::Shed);
+    }
+
+    #[test]
+    fn leaves_a_level_below_half_its_threshold() {
+        let policy = policy();
+        let level = |backlog, current| policy.level(&status(&[(Some(backlog), 0)]), current);
+
+        assert_eq!(
+            level(3_999, BackpressureLevel::Shed),
+            BackpressureLevel::Shed
+        );
+        assert_eq!(
+            level(2_000, BackpressureLevel::Paused),
+            BackpressureLevel::Paused
+        );
+        assert_eq!(
+            level(1_999, BackpressureLevel::Paused),
+            BackpressureLevel::Shed
+        );
+
+        assert_eq!(level(500, BackpressureLevel::Shed), BackpressureLevel::Shed);
+        assert_eq!(level(499, BackpressureLevel::Shed), BackpressureLevel::None);
+        // Pausing held shedding too, so both levels resume at half.
+        assert_eq!(
+            level(500, BackpressureLevel::Paused),
+            BackpressureLevel::Shed
+        );
+    }
+
+    #[test]
+    fn ignores_unset_thresholds() {
+        let policy = BackpressurePolicy {
+            pause_backlog: None,
+            ..policy()
+        };
+        let backlog = status(&[(Some(1_000_000), 0)]);
+        assert_eq!(
+            policy.level(&backlog, BackpressureLevel::None),
+            BackpressureLevel::Shed
+        );
+        assert!(!BackpressurePolicy::default().is_enabled());
+        assert_eq!(
+            BackpressurePolicy::default().level(&backlog, BackpressureLevel::None),
+            BackpressureLevel::None
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::types::IndexEvent;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Name of the `blocks_meta` filter the engine adds to learn block times.
+pub const BLOCK_TIME_FILTER: &str = "block_times";
+
+/// How many slots an event may wait for its block meta before it is released stamped
+/// with its ingest time instead.
+pub const DEFAULT_BLOCK_TIME_MAX_WAIT_SLOTS: u64 = 16;
+
+/// Slot -> block time cache that holds transaction, status and account events until the block
+/// meta for their slot arrives, then stamps them with the chain's block time.
+///
+/// Geyser sends a slot's block meta after its transactions and account writes, so events
+/// are buffered per slot. Slots that never get a block meta (skipped or dead) are released
+/// once they fall `max_wait_slots` behind the newest slot seen.
+pub struct BlockTimeBuffer {
+    max_wait_slots: u64,
+    block_times: BTreeMap<u64, DateTime<Utc>>,
+    pending: BTreeMap<u64, Vec<IndexEvent>>,
+    newest_slot: u64,
+}
+
+impl BlockTimeBuffer {
+    pub fn new(max_wait_slots: u64) -> Self {
+        Self {
+            max_wait_slots,
+            block_times: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            newest_slot: 0,
+        }
+    }
+
+    pub fn block_time(&self, slot: u64) -> Option<DateTime<Utc>> {
+        self.block_times.get(&slot).copied()
+    }
+
+    /// Stamps `event` if its block time is known, otherwise holds it. Returns the events
+    /// that are ready to publish, in slot order.
+    pub fn push(&mut self, event: IndexEvent) -> Vec<IndexEvent> {
+        let Some(slot) = event_slot(&event) else {
+            return vec![event];
+        };
+        self.newest_slot = self.newest_slot.max(slot);
+
+        let mut ready = self.release_stale();
+        match self.block_time(slot) {
+            Some(block_time) => ready.push(stamp(event, block_time)),
+            None => self.pending.entry(slot).or_default().push(event),
+        }
+        ready
+    }
+
+    /// Records the block time of `slot` and releases the events waiting for it.
+    pub fn on_block_meta(
+        &mut self,
+        slot: u64,
+        block_time: Option<DateTime<Utc>>,
+    ) -> Vec<IndexEvent> {
+        self.newest_slot = self.newest_slot.max(slot);
+        let mut ready = self.release_stale();
+
+        match block_time {
+            Some(block_time) => {
+                self.block_times.insert(slot, block_time);
+                if let Some(events) = self.pending.remove(&slot) {
+                    ready.extend(events.into_iter().map(|event| stamp(event, block_time)));
+                }
+            }
+            None => {
+                if let Some(events) = self.pending.remove(&slot) {
+                    warn!("Block meta for slot {} has no block time", slot);
+                    ready.extend(events);
+                }
+            }
+        }
+
+        let cutoff = self.newest_slot.saturating_sub(self.max_wait_slots);
+        while let Some((&oldest, _)) = self.block_times.first_key_value() {
+            if oldest >= cutoff {
+                break;
+            }
+            self.block_times.pop_first();
+        }
+
+        ready
+    }
+
+    /// Releases events for slots that fell too far behind to still expect a block meta.
+    /// They keep their ingest time as `timestamp`.
+    fn release_stale(&mut self) -> Vec<IndexEvent> {
+        let cutoff = self.newest_slot.saturating_sub(self.max_wait_slots);
+        let mut ready = Vec::new();
+        while let Some((&oldest, _)) = self.pending.first_key_value() {
+            if oldest >= cutoff {
+                break;
+            }
+            if let Some((slot, events)) = self.pending.pop_first() {
+                warn!(
+                    "No block time for slot {} after {} slots, using ingest time for {} events",
+                    slot,
+                    self.max_wait_slots,
+                    events.len()
+                );
+                ready.extend(events);
+            }
+        }
+        ready
+    }
+}
+
+fn event_slot(event: &IndexEvent) -> Option<u64> {
+    match event {
+        IndexEvent::Transaction(transaction) => Some(transaction.slot),
+        IndexEvent::Account(account) => Some(account.slot),
+        IndexEvent::TransactionStatus(status) => Some(status.slot),
+        _ => None,
+    }
+}
+
+fn stamp(event: IndexEvent, block_time: DateTime<Utc>) -> IndexEvent {
+    match event {
+        IndexEvent::Transaction(mut transaction) => {
+            transaction.timestamp = block_time;
+            IndexEvent::Transaction(transaction)
+        }
+        IndexEvent::Account(mut account) => {
+            account.timestamp = block_time;
+            IndexEvent::Account(account)
+        }
+        IndexEvent::TransactionStatus(mut status) => {
+            status.timestamp = block_time;
+            IndexEvent::TransactionStatus(status)
+        }
+        other => other,
+    }
+}
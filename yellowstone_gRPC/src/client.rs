@@ -1,271 +1,892 @@
-use crate::types::{IndexEvent, SolanaAccount, SolanaTransaction};
-use anyhow::Result;
-use base64::{engine::general_purpose, Engine as _};
-use bs58;
-use chrono::Utc;
-use futures::{channel::mpsc, Sink, Stream, StreamExt};
-use redis::{Commands, Connection};
-use tonic::{transport::ClientTlsConfig, Status};
-use tracing::{error, info};
-use yellowstone_grpc_client::{
-    GeyserGrpcBuilderError, GeyserGrpcClient, GeyserGrpcClientResult, Interceptor,
-};
-use yellowstone_grpc_proto::geyser::{
-    subscribe_update, SubscribeRequest, SubscribeUpdate, SubscribeUpdateAccount,
-    SubscribeUpdateSlot, SubscribeUpdateTransaction,
-};
-
-pub struct YellowstoneClient;
-
-impl YellowstoneClient {
-    pub async fn create_yellowstone_client(
-        endpoint: &str,
-        token: Option<String>,
-    ) -> Result<GeyserGrpcClient<impl Interceptor>, GeyserGrpcBuilderError> {
-        let builder = GeyserGrpcClient::build_from_shared(endpoint.to_string())?
-            .tls_config(ClientTlsConfig::new().with_native_roots())?
-            .x_token(token)?;
-
-        let client = builder.connect().await?;
-        return Ok(client);
-    }
-
-    pub async fn subscribe(
-        client: &mut GeyserGrpcClient<impl Interceptor>,
-    ) -> GeyserGrpcClientResult<(
-        impl Sink<SubscribeRequest, Error = mpsc::SendError>,
-        impl Stream<Item = Result<SubscribeUpdate, Status>>,
-    )> {
-        client.subscribe().await
-    }
-
-    pub async fn handle_stream(
-        mut stream: impl Stream<Item = Result<SubscribeUpdate, Status>> + Unpin,
-        redis_client_connection: &mut Connection,
-        stream_name: &str,
-    ) -> Result<()> {
-        while let Some(message) = stream.next().await {
-            match message {
-                Ok(update) => {
-                    Self::process_update(update, redis_client_connection, stream_name).await?;
-                }
-                Err(error) => {
-                    error!("Stream Error: {}", error);
-                }
-            }
-        }
-        Ok(())
-    }
-
-    pub async fn process_update(
-        update: SubscribeUpdate,
-        redis_client_connection: &mut Connection,
-        stream_name: &str,
-    ) -> Result<()> {
-        match update.update_oneof {
-            Some(subscribe_update::UpdateOneof::Account(account)) => {
-                Self::handle_account_update(account, redis_client_connection, stream_name).await?;
-            }
-            Some(subscribe_update::UpdateOneof::Transaction(transaction)) => {
-                Self::handle_transaction_update(transaction, redis_client_connection, stream_name)
-                    .await?;
-            }
-            Some(subscribe_update::UpdateOneof::Slot(slot)) => {
-                Self::handle_slot_update(slot, redis_client_connection).await?;
-            }
-            _ => {}
-        }
-
-        Ok(())
-    }
-
-    pub async fn handle_account_update(
-        account_update: SubscribeUpdateAccount,
-        redis_client_connection: &mut Connection,
-        stream_name: &str,
-    ) -> Result<()> {
-        if let Some(solana_account) = Self::to_solana_account(account_update) {
-            info!(
-                "Account: pubkey={}, lamports={}, owner={}, executable={}",
-                solana_account.pubkey,
-                solana_account.lamports,
-                solana_account.owner,
-                solana_account.executable
-            );
-
-            let account_payload = serde_json::to_string(&IndexEvent::Account(solana_account))?;
-            let payload = [("payload", account_payload)];
-            redis_client_connection.xadd(stream_name, "*", &payload)?;
-        }
-
-        Ok(())
-    }
-
-    pub async fn handle_transaction_update(
-        transaction_update: SubscribeUpdateTransaction,
-        redis_client_connection: &mut Connection,
-        stream_name: &str,
-    ) -> Result<()> {
-        if let Some(solana_transaction) = Self::to_solana_transaction(transaction_update) {
-            info!(
-                "Transaction: signature={}, slot={}, success={}",
-                solana_transaction.signature, solana_transaction.slot, solana_transaction.success
-            );
-
-            let transaction_payload =
-                serde_json::to_string(&IndexEvent::Transaction(solana_transaction))?;
-            let payload = [("payload", transaction_payload)];
-            redis_client_connection.xadd(stream_name, "*", &payload)?;
-        }
-
-        Ok(())
-    }
-
-    pub async fn handle_slot_update(
-        slot_update: SubscribeUpdateSlot,
-        redis_client_connection: &mut Connection,
-    ) -> Result<()> {
-        info!("Slot: {:?}", slot_update.slot);
-        redis_client_connection.set("current_slot", slot_update.slot)?;
-
-        Ok(())
-    }
-
-    fn to_solana_transaction(
-        transaction_update: SubscribeUpdateTransaction,
-    ) -> Option<SolanaTransaction> {
-        if let Some(transaction_info) = transaction_update.transaction {
-            let signature = bs58::encode(&transaction_info.signature).into_string();
-
-            let (
-                success,
-                fee,
-                compute_units_consumed,
-                instructions,
-                account_keys,
-                log_messages,
-                pre_balances,
-                post_balances,
-            ) = if let (Some(transaction), Some(meta)) = (
-                transaction_info.transaction.as_ref(),
-                transaction_info.meta.as_ref(),
-            ) {
-                let success = meta.err.is_none();
-                let fee = Some(meta.fee);
-                let compute_units_consumed = meta.compute_units_consumed;
-
-                let mut instructions = Vec::new();
-                if let Some(message) = transaction.message.as_ref() {
-                    for instruction in &message.instructions {
-                        let program_id_index = instruction.program_id_index as usize;
-                        let program_id = if program_id_index < message.account_keys.len() {
-                            bs58::encode(&message.account_keys[program_id_index]).into_string()
-                        } else {
-                            String::new()
-                        };
-
-                        let accounts: Vec<String> = instruction
-                            .accounts
-                            .iter()
-                            .filter_map(|&idx| {
-                                message
-                                    .account_keys
-                                    .get(idx as usize)
-                                    .map(|key| bs58::encode(key).into_string())
-                            })
-                            .collect();
-
-                        instructions.push(crate::types::TransactionInstruction {
-                            program_id,
-                            accounts,
-                            data: general_purpose::STANDARD.encode(&instruction.data),
-                        });
-                    }
-                }
-
-                let account_keys: Vec<String> = if let Some(message) = transaction.message.as_ref()
-                {
-                    message
-                        .account_keys
-                        .iter()
-                        .map(|key| bs58::encode(key).into_string())
-                        .collect()
-                } else {
-                    Vec::new()
-                };
-
-                let log_messages: Vec<String> = meta.log_messages.clone();
-
-                let pre_balances = meta.pre_balances.clone();
-                let post_balances = meta.post_balances.clone();
-
-                (
-                    success,
-                    fee,
-                    compute_units_consumed,
-                    instructions,
-                    account_keys,
-                    log_messages,
-                    pre_balances,
-                    post_balances,
-                )
-            } else {
-                (
-                    false,
-                    None,
-                    None,
-                    Vec::new(),
-                    Vec::new(),
-                    Vec::new(),
-                    Vec::new(),
-                    Vec::new(),
-                )
-            };
-
-            Some(SolanaTransaction {
-                signature,
-                slot: transaction_update.slot,
-                is_vote: transaction_info.is_vote,
-                index: transaction_info.index,
-                success,
-                fee,
-                compute_units_consumed,
-                instructions,
-                account_keys,
-                log_messages,
-                pre_balances,
-                post_balances,
-                timestamp: Utc::now(),
-            })
-        } else {
-            None
-        }
-    }
-
-    fn to_solana_account(account_update: SubscribeUpdateAccount) -> Option<SolanaAccount> {
-        if let Some(account_info) = account_update.account {
-            let pubkey = bs58::encode(&account_info.pubkey).into_string();
-            let owner = bs58::encode(&account_info.owner).into_string();
-            let data = general_purpose::STANDARD.encode(&account_info.data);
-
-            let txn_signature = account_info
-                .txn_signature
-                .map(|sig| bs58::encode(&sig).into_string());
-
-            Some(SolanaAccount {
-                pubkey,
-                lamports: account_info.lamports,
-                owner,
-                executable: account_info.executable,
-                rent_epoch: account_info.rent_epoch,
-                data,
-                write_version: account_info.write_version,
-                slot: account_update.slot,
-                txn_signature,
-                timestamp: Utc::now(),
-            })
-        } else {
-            None
-        }
-    }
-}
+use crate::backpressure::BackpressureLevel;
+use crate::block_time::{BlockTimeBuffer, BLOCK_TIME_FILTER, DEFAULT_BLOCK_TIME_MAX_WAIT_SLOTS};
+use crate::compute_budget::{self, COMPUTE_BUDGET_PROGRAM_ID};
+use crate::control::{describe_request, ControlMessage, SubscriptionCommand};
+use crate::dedup::{Deduplicator, DEFAULT_DEDUP_SLOT_WINDOW};
+use crate::entries::EntryBuffer;
+use crate::keepalive::KeepalivePolicy;
+use crate::publisher::Publisher;
+use crate::reconnect::{ReconnectPolicy, SlotCursor};
+use crate::session::{EndpointConfig, EndpointWorker, SessionUpdate, StreamMode};
+use crate::subscriptions::TransactionMode;
+use crate::types::{
+    BlockEntry, ComputeBudget, IndexEvent, ReturnData, Reward, SlotStatus, SlotUpdate,
+    SolanaAccount, SolanaBlock, SolanaTransaction, TokenBalance, TokenBalanceChange,
+    TransactionErrorInfo, TransactionStatus,
+};
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose, Engine as _};
+use bs58;
+use chrono::{DateTime, Utc};
+use futures::{channel::mpsc, Sink, Stream};
+use solana_instruction::error::InstructionError;
+use solana_transaction_error::TransactionError;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::{mpsc as tokio_mpsc, watch};
+use tokio::task::JoinSet;
+use tonic::{transport::ClientTlsConfig, Status};
+use tracing::{debug, info};
+use yellowstone_grpc_client::{
+    GeyserGrpcBuilderError, GeyserGrpcClient, GeyserGrpcClientResult, Interceptor,
+};
+use yellowstone_grpc_proto::convert_from::create_tx_error;
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update, SlotStatus as GeyserSlotStatus, SubscribeRequest,
+    SubscribeRequestFilterSlots, SubscribeUpdate, SubscribeUpdateAccount, SubscribeUpdateBlock,
+    SubscribeUpdateBlockMeta, SubscribeUpdateEntry, SubscribeUpdateSlot,
+    SubscribeUpdateTransaction, SubscribeUpdateTransactionStatus,
+};
+use yellowstone_grpc_proto::solana::storage::confirmed_block::{
+    Message, Reward as GeyserReward, RewardType, TokenBalance as GeyserTokenBalance,
+    TransactionError as GeyserTransactionError, TransactionStatusMeta,
+};
+
+pub struct YellowstoneClient;
+
+/// Name of the `slots` filter the engine adds to follow slot status progression.
+pub const SLOT_STATUS_FILTER: &str = "slot_status";
+
+/// Updates buffered between the endpoint workers and the Redis publisher.
+const UPDATE_CHANNEL_CAPACITY: usize = 10_000;
+
+/// How a subscription stream ended when it did not fail with a processing error.
+#[derive(Debug)]
+pub enum StreamEnd {
+    Closed,
+    Error(Status),
+    /// A client ping went unanswered for longer than the keepalive timeout.
+    KeepaliveTimeout(Duration),
+}
+
+/// Subscription state that outlives a single gRPC session: the request as amended by
+/// control commands, the resume cursor, the events waiting for their block time and the
+/// entries waiting for their block.
+pub struct SubscriptionState {
+    pub request: SubscribeRequest,
+    pub cursor: SlotCursor,
+    pub block_times: BlockTimeBuffer,
+    pub entries: EntryBuffer,
+    pub transaction_mode: TransactionMode,
+    commands: Option<tokio_mpsc::Receiver<ControlMessage>>,
+}
+
+impl SubscriptionState {
+    /// Adds a `blocks_meta` filter to `request` if it has none, since block metas are
+    /// what transactions and accounts are timestamped with, and its own `slots` filter so
+    /// every slot status (including dead slots) reaches the stream. The request is then
+    /// rewritten for `transaction_mode`.
+    pub fn new(
+        mut request: SubscribeRequest,
+        transaction_mode: TransactionMode,
+        commands: Option<tokio_mpsc::Receiver<ControlMessage>>,
+    ) -> Self {
+        transaction_mode.apply(&mut request);
+        if request.blocks_meta.is_empty() {
+            request
+                .blocks_meta
+                .insert(BLOCK_TIME_FILTER.to_string(), Default::default());
+        }
+        // Added next to any slots filter of the spec: one that filters by commitment
+        // would hide the processed, dead and finalized statuses fork tracking relies on.
+        request.slots.insert(
+            SLOT_STATUS_FILTER.to_string(),
+            SubscribeRequestFilterSlots {
+                filter_by_commitment: Some(false),
+                interslot_updates: Some(true),
+            },
+        );
+
+        Self {
+            request,
+            cursor: SlotCursor::default(),
+            block_times: BlockTimeBuffer::new(DEFAULT_BLOCK_TIME_MAX_WAIT_SLOTS),
+            entries: EntryBuffer::new(DEFAULT_BLOCK_TIME_MAX_WAIT_SLOTS),
+            transaction_mode,
+            commands,
+        }
+    }
+
+    /// Waits for the next control command; never resolves without a control channel.
+    async fn next_command(&mut self) -> Option<ControlMessage> {
+        let Some(commands) = self.commands.as_mut() else {
+            return std::future::pending().await;
+        };
+        let message = commands.recv().await;
+        if message.is_none() {
+            self.commands = None;
+        }
+        message
+    }
+}
+
+impl YellowstoneClient {
+    pub async fn create_yellowstone_client(
+        endpoint: &str,
+        token: Option<String>,
+    ) -> Result<GeyserGrpcClient<impl Interceptor>, GeyserGrpcBuilderError> {
+        let builder = GeyserGrpcClient::build_from_shared(endpoint.to_string())?
+            .tls_config(ClientTlsConfig::new().with_native_roots())?
+            .x_token(token)?;
+
+        let client = builder.connect().await?;
+        Ok(client)
+    }
+
+    pub async fn subscribe(
+        client: &mut GeyserGrpcClient<impl Interceptor>,
+    ) -> GeyserGrpcClientResult<(
+        impl Sink<SubscribeRequest, Error = mpsc::SendError>,
+        impl Stream<Item = Result<SubscribeUpdate, Status>>,
+    )> {
+        client.subscribe().await
+    }
+
+    /// Runs the subscription until a fatal error. Each endpoint worker rebuilds its gRPC
+    /// client and resubscribes from the last fully pushed slot whenever its stream drops;
+    /// in [`StreamMode::Race`] every endpoint streams at once and the first copy of each
+    /// update wins. Control commands received by `state` are merged into the request and
+    /// pushed to every live stream without restarting it.
+    pub async fn run_with_reconnect(
+        endpoints: Vec<EndpointConfig>,
+        mode: StreamMode,
+        mut state: SubscriptionState,
+        publisher: &Publisher,
+        policy: ReconnectPolicy,
+        keepalive: KeepalivePolicy,
+    ) -> Result<()> {
+        if endpoints.is_empty() {
+            bail!("No Yellowstone endpoints configured");
+        }
+
+        let (request_tx, request_rx) = watch::channel(state.request.clone());
+        let (resume_tx, resume_rx) = watch::channel(state.cursor.resume_slot());
+        let (updates_tx, mut updates_rx) = tokio_mpsc::channel(UPDATE_CHANNEL_CAPACITY);
+
+        let worker_endpoints = match mode {
+            StreamMode::Failover => vec![endpoints],
+            StreamMode::Race => endpoints
+                .into_iter()
+                .map(|endpoint| vec![endpoint])
+                .collect(),
+        };
+        let mut workers = JoinSet::new();
+        for endpoints in worker_endpoints {
+            let worker = EndpointWorker::new(
+                endpoints,
+                request_rx.clone(),
+                resume_rx.clone(),
+                updates_tx.clone(),
+                policy.clone(),
+                keepalive,
+            );
+            workers.spawn(worker.run());
+        }
+        drop(updates_tx);
+
+        let mut dedup = Deduplicator::new(DEFAULT_DEDUP_SLOT_WINDOW);
+        let mut backpressure = publisher.backpressure();
+        loop {
+            // While paused the update channel fills up and the workers stop reading from
+            // their gRPC streams.
+            let paused = *backpressure.borrow_and_update() == BackpressureLevel::Paused;
+            tokio::select! {
+                update = updates_rx.recv(), if !paused => {
+                    let Some(SessionUpdate { endpoint, mut update }) = update else {
+                        break;
+                    };
+                    if !dedup.first_seen(&update) {
+                        debug!("Dropping duplicate update from {}", endpoint);
+                        continue;
+                    }
+                    dedup.strip_seen(&mut update);
+                    Self::process_update(update, publisher, &mut state).await?;
+                    resume_tx.send_if_modified(|resume_slot| {
+                        let next = state.cursor.resume_slot();
+                        let modified = *resume_slot != next;
+                        *resume_slot = next;
+                        modified
+                    });
+                }
+                Ok(()) = backpressure.changed() => {}
+                Some(message) = state.next_command() => {
+                    if Self::handle_command(message, &mut state.request, state.transaction_mode) {
+                        request_tx.send_replace(state.request.clone());
+                    }
+                }
+            }
+        }
+
+        // Every worker has exited; surface the first failure.
+        while let Some(result) = workers.join_next().await {
+            result??;
+        }
+        bail!("All Yellowstone endpoint workers stopped")
+    }
+
+    /// Applies a control command to the request and reports the outcome. Returns whether
+    /// the request changed and has to be pushed to the live streams.
+    fn handle_command(
+        message: ControlMessage,
+        request: &mut SubscribeRequest,
+        transaction_mode: TransactionMode,
+    ) -> bool {
+        let ControlMessage { command, reply } = message;
+
+        if command == SubscriptionCommand::Show {
+            let _ = reply.send(Ok(describe_request(request)));
+            return false;
+        }
+
+        if !command.apply(request, transaction_mode) {
+            let _ = reply.send(Ok(format!("{}: no change", command)));
+            return false;
+        }
+
+        info!("Updating live subscription: {}", command);
+        let _ = reply.send(Ok(format!("{}: applied", command)));
+        true
+    }
+
+    pub async fn process_update(
+        update: SubscribeUpdate,
+        publisher: &Publisher,
+        state: &mut SubscriptionState,
+    ) -> Result<()> {
+        let block_times = &mut state.block_times;
+        let created_at = update.created_at.as_ref().and_then(|created_at| {
+            DateTime::from_timestamp(created_at.seconds, created_at.nanos as u32)
+        });
+
+        let ready = match update.update_oneof {
+            Some(subscribe_update::UpdateOneof::Account(account)) => {
+                match Self::to_solana_account(account) {
+                    Some(solana_account) => block_times.push(IndexEvent::Account(solana_account)),
+                    None => Vec::new(),
+                }
+            }
+            Some(subscribe_update::UpdateOneof::Transaction(transaction)) => {
+                match Self::to_solana_transaction(transaction) {
+                    Some(solana_transaction) => {
+                        block_times.push(IndexEvent::Transaction(solana_transaction))
+                    }
+                    None => Vec::new(),
+                }
+            }
+            Some(subscribe_update::UpdateOneof::BlockMeta(block_meta)) => {
+                let entries = state.entries.take(block_meta.slot);
+                let block = Self::block_from_meta(block_meta, entries);
+                let mut ready = block_times.on_block_meta(block.slot, block.block_time);
+                ready.push(IndexEvent::Block(block));
+                ready
+            }
+            Some(subscribe_update::UpdateOneof::Block(block)) => Self::handle_block(block),
+            Some(subscribe_update::UpdateOneof::TransactionStatus(status)) => {
+                let status = Self::to_transaction_status(status);
+                block_times.push(IndexEvent::TransactionStatus(status))
+            }
+            Some(subscribe_update::UpdateOneof::Entry(entry)) => {
+                let slot = entry.slot;
+                state.entries.push(slot, Self::to_block_entry(entry));
+                Vec::new()
+            }
+            Some(subscribe_update::UpdateOneof::Slot(slot)) => {
+                match Self::handle_slot_update(slot, created_at, publisher).await? {
+                    Some(slot_update) => block_times.push(IndexEvent::Slot(slot_update)),
+                    None => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        for event in ready {
+            let slot = match &event {
+                IndexEvent::Transaction(transaction) => Some(transaction.slot),
+                IndexEvent::Account(account) => Some(account.slot),
+                _ => None,
+            };
+            if !publisher.sheds(&event) {
+                Self::publish_event(event, publisher).await?;
+            }
+            if let Some(slot) = slot {
+                state.cursor.observe(slot);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queues `event` on the publisher; it reaches the stream with the next pipeline.
+    pub async fn publish_event(event: IndexEvent, publisher: &Publisher) -> Result<()> {
+        match &event {
+            IndexEvent::Account(solana_account) => info!(
+                "Account: pubkey={}, lamports={}, owner={}, executable={}",
+                solana_account.pubkey,
+                solana_account.lamports,
+                solana_account.owner,
+                solana_account.executable
+            ),
+            IndexEvent::Transaction(solana_transaction) => info!(
+                "Transaction: signature={}, slot={}, success={}",
+                solana_transaction.signature, solana_transaction.slot, solana_transaction.success
+            ),
+            IndexEvent::Slot(slot_update) => info!(
+                "Slot: slot={}, parent={:?}, status={}",
+                slot_update.slot, slot_update.parent, slot_update.status
+            ),
+            IndexEvent::Block(block) => info!(
+                "Block: slot={}, blockhash={}, transactions={}",
+                block.slot, block.blockhash, block.executed_transaction_count
+            ),
+            IndexEvent::TransactionStatus(status) => info!(
+                "TransactionStatus: signature={}, slot={}, success={}",
+                status.signature, status.slot, status.success
+            ),
+        }
+
+        publisher.publish(&event).await
+    }
+
+    /// Converts a slot update into an event for the stream. Only the commitment
+    /// progression (processed, confirmed, finalized) and dead slots are emitted; the
+    /// intermediate interslot statuses are dropped.
+    pub async fn handle_slot_update(
+        slot_update: SubscribeUpdateSlot,
+        created_at: Option<DateTime<Utc>>,
+        publisher: &Publisher,
+    ) -> Result<Option<SlotUpdate>> {
+        let status = match GeyserSlotStatus::try_from(slot_update.status) {
+            Ok(GeyserSlotStatus::SlotProcessed) => SlotStatus::Processed,
+            Ok(GeyserSlotStatus::SlotConfirmed) => SlotStatus::Confirmed,
+            Ok(GeyserSlotStatus::SlotFinalized) => SlotStatus::Finalized,
+            Ok(GeyserSlotStatus::SlotDead) => SlotStatus::Dead,
+            _ => return Ok(None),
+        };
+
+        if status == SlotStatus::Processed {
+            publisher.set_current_slot(slot_update.slot).await?;
+        }
+
+        Ok(Some(SlotUpdate {
+            slot: slot_update.slot,
+            parent: slot_update.parent,
+            status,
+            dead_error: slot_update.dead_error,
+            timestamp: created_at.unwrap_or_else(Utc::now),
+        }))
+    }
+
+    /// Turns a full block update into its block event followed by the transactions and
+    /// account writes it carries, stamped with the block's own time.
+    fn handle_block(block: SubscribeUpdateBlock) -> Vec<IndexEvent> {
+        let ingested_at = Utc::now();
+        let block_time = block
+            .block_time
+            .and_then(|block_time| DateTime::from_timestamp(block_time.timestamp, 0));
+        let timestamp = block_time.unwrap_or(ingested_at);
+        let slot = block.slot;
+
+        let mut events = vec![IndexEvent::Block(SolanaBlock {
+            slot,
+            blockhash: block.blockhash,
+            parent_slot: block.parent_slot,
+            parent_blockhash: block.parent_blockhash,
+            block_height: block.block_height.map(|height| height.block_height),
+            block_time,
+            rewards: block
+                .rewards
+                .map(|rewards| rewards.rewards.iter().map(Self::to_reward).collect())
+                .unwrap_or_default(),
+            executed_transaction_count: block.executed_transaction_count,
+            entries_count: block.entries_count,
+            entries: (!block.entries.is_empty()).then(|| {
+                block
+                    .entries
+                    .into_iter()
+                    .map(Self::to_block_entry)
+                    .collect()
+            }),
+            updated_account_count: Some(block.updated_account_count),
+            timestamp,
+            ingested_at,
+        })];
+
+        for transaction in block.transactions {
+            let update = SubscribeUpdateTransaction {
+                transaction: Some(transaction),
+                slot,
+            };
+            if let Some(mut solana_transaction) = Self::to_solana_transaction(update) {
+                solana_transaction.timestamp = timestamp;
+                events.push(IndexEvent::Transaction(solana_transaction));
+            }
+        }
+        for account in block.accounts {
+            let update = SubscribeUpdateAccount {
+                account: Some(account),
+                slot,
+                is_startup: false,
+            };
+            if let Some(mut solana_account) = Self::to_solana_account(update) {
+                solana_account.timestamp = timestamp;
+                events.push(IndexEvent::Account(solana_account));
+            }
+        }
+
+        events
+    }
+
+    fn block_from_meta(
+        block_meta: SubscribeUpdateBlockMeta,
+        entries: Option<Vec<BlockEntry>>,
+    ) -> SolanaBlock {
+        let ingested_at = Utc::now();
+        let block_time = block_meta
+            .block_time
+            .and_then(|block_time| DateTime::from_timestamp(block_time.timestamp, 0));
+
+        SolanaBlock {
+            slot: block_meta.slot,
+            blockhash: block_meta.blockhash,
+            parent_slot: block_meta.parent_slot,
+            parent_blockhash: block_meta.parent_blockhash,
+            block_height: block_meta.block_height.map(|height| height.block_height),
+            block_time,
+            rewards: block_meta
+                .rewards
+                .map(|rewards| rewards.rewards.iter().map(Self::to_reward).collect())
+                .unwrap_or_default(),
+            executed_transaction_count: block_meta.executed_transaction_count,
+            entries_count: block_meta.entries_count,
+            entries,
+            updated_account_count: None,
+            timestamp: block_time.unwrap_or(ingested_at),
+            ingested_at,
+        }
+    }
+
+    fn to_transaction_status(status: SubscribeUpdateTransactionStatus) -> TransactionStatus {
+        let ingested_at = Utc::now();
+        let error = status.err.as_ref().map(Self::to_transaction_error);
+
+        TransactionStatus {
+            signature: bs58::encode(&status.signature).into_string(),
+            slot: status.slot,
+            index: status.index,
+            is_vote: status.is_vote,
+            success: error.is_none(),
+            error,
+            timestamp: ingested_at,
+            ingested_at,
+        }
+    }
+
+    fn to_block_entry(entry: SubscribeUpdateEntry) -> BlockEntry {
+        BlockEntry {
+            index: entry.index,
+            num_hashes: entry.num_hashes,
+            hash: bs58::encode(&entry.hash).into_string(),
+            executed_transaction_count: entry.executed_transaction_count,
+            starting_transaction_index: entry.starting_transaction_index,
+        }
+    }
+
+    fn to_solana_transaction(
+        transaction_update: SubscribeUpdateTransaction,
+    ) -> Option<SolanaTransaction> {
+        let transaction_info = transaction_update.transaction?;
+
+        // Stamped with the block time once the slot's block meta arrives.
+        let ingested_at = Utc::now();
+        let mut solana_transaction = SolanaTransaction {
+            signature: bs58::encode(&transaction_info.signature).into_string(),
+            slot: transaction_update.slot,
+            is_vote: transaction_info.is_vote,
+            index: transaction_info.index,
+            timestamp: ingested_at,
+            ingested_at,
+            ..Default::default()
+        };
+
+        let (Some(transaction), Some(meta)) = (
+            transaction_info.transaction.as_ref(),
+            transaction_info.meta.as_ref(),
+        ) else {
+            return Some(solana_transaction);
+        };
+
+        solana_transaction.success = meta.err.is_none();
+        solana_transaction.error = meta.err.as_ref().map(Self::to_transaction_error);
+        solana_transaction.fee = Some(meta.fee);
+        solana_transaction.compute_units_consumed = meta.compute_units_consumed;
+        solana_transaction.cost_units = meta.cost_units;
+
+        let (resolved_keys, is_writable, is_signer) = match transaction.message.as_ref() {
+            Some(message) => Self::resolve_account_keys(message, meta),
+            None => Default::default(),
+        };
+
+        let mut compute_budget = ComputeBudget::default();
+        let mut builtin_instructions = 0;
+        let mut other_instructions = 0;
+        if let Some(message) = transaction.message.as_ref() {
+            // Legacy messages have no version; v0 is the only versioned format so far.
+            solana_transaction.version = message.versioned.then_some(0);
+
+            for instruction in &message.instructions {
+                let resolved = Self::to_transaction_instruction(
+                    instruction.program_id_index,
+                    &instruction.accounts,
+                    &instruction.data,
+                    &resolved_keys,
+                );
+                if resolved.program_id == COMPUTE_BUDGET_PROGRAM_ID {
+                    compute_budget::apply_instruction(&instruction.data, &mut compute_budget);
+                }
+                if compute_budget::is_builtin(&resolved.program_id) {
+                    builtin_instructions += 1;
+                } else {
+                    other_instructions += 1;
+                }
+                solana_transaction.instructions.push(resolved);
+            }
+
+            for inner in &meta.inner_instructions {
+                for instruction in &inner.instructions {
+                    let resolved = Self::to_transaction_instruction(
+                        instruction.program_id_index,
+                        &instruction.accounts,
+                        &instruction.data,
+                        &resolved_keys,
+                    );
+                    solana_transaction
+                        .inner_instructions
+                        .push(crate::types::InnerInstruction {
+                            parent_index: inner.index,
+                            stack_height: instruction.stack_height,
+                            program_id: resolved.program_id,
+                            accounts: resolved.accounts,
+                            data: resolved.data,
+                        });
+                }
+            }
+        }
+
+        let priority_fee =
+            compute_budget::priority_fee(&compute_budget, builtin_instructions, other_instructions);
+        solana_transaction.priority_fee = Some(priority_fee);
+        solana_transaction.base_fee = Some(meta.fee.saturating_sub(priority_fee));
+        solana_transaction.compute_budget = compute_budget;
+
+        solana_transaction.account_keys = resolved_keys
+            .iter()
+            .map(|key| bs58::encode(key).into_string())
+            .collect();
+        solana_transaction.is_writable = is_writable;
+        solana_transaction.is_signer = is_signer;
+
+        solana_transaction.log_messages = meta.log_messages.clone();
+        solana_transaction.pre_balances = meta.pre_balances.clone();
+        solana_transaction.post_balances = meta.post_balances.clone();
+
+        solana_transaction.pre_token_balances =
+            Self::to_token_balances(&meta.pre_token_balances, &solana_transaction.account_keys);
+        solana_transaction.post_token_balances =
+            Self::to_token_balances(&meta.post_token_balances, &solana_transaction.account_keys);
+        solana_transaction.token_balance_changes = Self::token_balance_changes(
+            &solana_transaction.pre_token_balances,
+            &solana_transaction.post_token_balances,
+        );
+
+        solana_transaction.rewards = meta.rewards.iter().map(Self::to_reward).collect();
+        solana_transaction.return_data = meta.return_data.as_ref().map(|return_data| ReturnData {
+            program_id: bs58::encode(&return_data.program_id).into_string(),
+            data: general_purpose::STANDARD.encode(&return_data.data),
+        });
+
+        Some(solana_transaction)
+    }
+
+    fn to_reward(reward: &GeyserReward) -> Reward {
+        Reward {
+            pubkey: reward.pubkey.clone(),
+            lamports: reward.lamports,
+            post_balance: reward.post_balance,
+            reward_type: RewardType::try_from(reward.reward_type)
+                .ok()
+                .filter(|reward_type| *reward_type != RewardType::Unspecified)
+                .map(|reward_type| reward_type.as_str_name().to_string()),
+            commission: reward.commission.parse().ok(),
+        }
+    }
+
+    /// Returns the static keys followed by the addresses loaded from lookup tables
+    /// (writable, then readonly), which is the order compiled instruction indexes refer
+    /// to, along with each key's writable and signer flags.
+    fn resolve_account_keys(
+        message: &Message,
+        meta: &TransactionStatusMeta,
+    ) -> (Vec<Vec<u8>>, Vec<bool>, Vec<bool>) {
+        let header = message.header.unwrap_or_default();
+        let num_static = message.account_keys.len();
+        let num_signers = header.num_required_signatures as usize;
+        let num_writable_signers =
+            num_signers.saturating_sub(header.num_readonly_signed_accounts as usize);
+        let num_writable_unsigned = num_static
+            .saturating_sub(num_signers)
+            .saturating_sub(header.num_readonly_unsigned_accounts as usize);
+
+        let mut keys = message.account_keys.clone();
+        let mut is_writable: Vec<bool> = (0..num_static)
+            .map(|index| {
+                if index < num_signers {
+                    index < num_writable_signers
+                } else {
+                    index - num_signers < num_writable_unsigned
+                }
+            })
+            .collect();
+        let mut is_signer: Vec<bool> = (0..num_static).map(|index| index < num_signers).collect();
+
+        for (addresses, writable) in [
+            (&meta.loaded_writable_addresses, true),
+            (&meta.loaded_readonly_addresses, false),
+        ] {
+            keys.extend(addresses.iter().cloned());
+            is_writable.extend(std::iter::repeat_n(writable, addresses.len()));
+            is_signer.extend(std::iter::repeat_n(false, addresses.len()));
+        }
+
+        (keys, is_writable, is_signer)
+    }
+
+    /// Decodes the bincode-encoded `TransactionError`, keeping the failing instruction and
+    /// custom program error code when there is one.
+    fn to_transaction_error(err: &GeyserTransactionError) -> TransactionErrorInfo {
+        let error = match create_tx_error(Some(err)) {
+            Ok(Some(error)) => error,
+            _ => {
+                return TransactionErrorInfo {
+                    kind: "Unknown".to_string(),
+                    instruction_index: None,
+                    instruction_error: None,
+                    custom_code: None,
+                    message: format!(
+                        "undecodable error: {}",
+                        general_purpose::STANDARD.encode(&err.err)
+                    ),
+                }
+            }
+        };
+
+        let (instruction_index, instruction_error, custom_code) = match &error {
+            TransactionError::InstructionError(index, instruction_error) => {
+                let custom_code = match instruction_error {
+                    InstructionError::Custom(code) => Some(*code),
+                    _ => None,
+                };
+                (
+                    Some(*index),
+                    Some(variant_name(instruction_error)),
+                    custom_code,
+                )
+            }
+            _ => (None, None, None),
+        };
+
+        TransactionErrorInfo {
+            kind: variant_name(&error),
+            instruction_index,
+            instruction_error,
+            custom_code,
+            message: error.to_string(),
+        }
+    }
+
+    fn to_token_balances(
+        balances: &[GeyserTokenBalance],
+        account_keys: &[String],
+    ) -> Vec<TokenBalance> {
+        balances
+            .iter()
+            .map(|balance| {
+                let ui_token_amount = balance.ui_token_amount.clone().unwrap_or_default();
+                TokenBalance {
+                    account_index: balance.account_index,
+                    account: account_keys
+                        .get(balance.account_index as usize)
+                        .cloned()
+                        .unwrap_or_default(),
+                    mint: balance.mint.clone(),
+                    owner: balance.owner.clone(),
+                    program_id: balance.program_id.clone(),
+                    amount: ui_token_amount.amount.parse().unwrap_or_default(),
+                    ui_amount: ui_token_amount.ui_amount,
+                    decimals: ui_token_amount.decimals,
+                }
+            })
+            .collect()
+    }
+
+    /// Nets pre and post token balances per `(owner, mint)`. Token accounts created or
+    /// closed by the transaction only appear on one side and count as zero on the other.
+    fn token_balance_changes(
+        pre_token_balances: &[TokenBalance],
+        post_token_balances: &[TokenBalance],
+    ) -> Vec<TokenBalanceChange> {
+        let mut deltas: BTreeMap<(&str, &str), (i128, u32)> = BTreeMap::new();
+        for (balances, sign) in [(pre_token_balances, -1), (post_token_balances, 1)] {
+            for balance in balances {
+                let entry = deltas
+                    .entry((balance.owner.as_str(), balance.mint.as_str()))
+                    .or_insert((0, balance.decimals));
+                entry.0 += sign * balance.amount as i128;
+            }
+        }
+
+        deltas
+            .into_iter()
+            .filter(|(_, (delta, _))| *delta != 0)
+            .map(|((owner, mint), (delta, decimals))| TokenBalanceChange {
+                owner: owner.to_string(),
+                mint: mint.to_string(),
+                decimals,
+                delta,
+                ui_delta: delta as f64 / 10f64.powi(decimals as i32),
+            })
+            .collect()
+    }
+
+    /// Resolves a compiled instruction's account indexes against the message's keys.
+    fn to_transaction_instruction(
+        program_id_index: u32,
+        account_indexes: &[u8],
+        data: &[u8],
+        account_keys: &[Vec<u8>],
+    ) -> crate::types::TransactionInstruction {
+        let program_id = account_keys
+            .get(program_id_index as usize)
+            .map(|key| bs58::encode(key).into_string())
+            .unwrap_or_default();
+
+        let accounts: Vec<String> = account_indexes
+            .iter()
+            .filter_map(|&idx| {
+                account_keys
+                    .get(idx as usize)
+                    .map(|key| bs58::encode(key).into_string())
+            })
+            .collect();
+
+        crate::types::TransactionInstruction {
+            program_id,
+            accounts,
+            data: general_purpose::STANDARD.encode(data),
+        }
+    }
+
+    fn to_solana_account(account_update: SubscribeUpdateAccount) -> Option<SolanaAccount> {
+        if let Some(account_info) = account_update.account {
+            let pubkey = bs58::encode(&account_info.pubkey).into_string();
+            let owner = bs58::encode(&account_info.owner).into_string();
+            let data = general_purpose::STANDARD.encode(&account_info.data);
+
+            let txn_signature = account_info
+                .txn_signature
+                .map(|sig| bs58::encode(&sig).into_string());
+
+            let ingested_at = Utc::now();
+            Some(SolanaAccount {
+                pubkey,
+                lamports: account_info.lamports,
+                owner,
+                executable: account_info.executable,
+                rent_epoch: account_info.rent_epoch,
+                data,
+                write_version: account_info.write_version,
+                slot: account_update.slot,
+                txn_signature,
+                timestamp: ingested_at,
+                ingested_at,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Name of an enum variant, taken from its `Debug` output (`Custom(6001)` -> `Custom`).
+fn variant_name(value: &impl std::fmt::Debug) -> String {
+    let debug = format!("{:?}", value);
+    debug
+        .split(['(', ' ', '{'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(account: &str, owner: &str, mint: &str, amount: u64) -> TokenBalance {
+        TokenBalance {
+            account_index: 0,
+            account: account.to_string(),
+            mint: mint.to_string(),
+            owner: owner.to_string(),
+            program_id: "token".to_string(),
+            amount,
+            ui_amount: amount as f64 / 1e6,
+            decimals: 6,
+        }
+    }
+
+    fn deltas(changes: &[TokenBalanceChange]) -> Vec<(&str, &str, i128)> {
+        changes
+            .iter()
+            .map(|change| (change.owner.as_str(), change.mint.as_str(), change.delta))
+            .collect()
+    }
+
+    #[test]
+    fn nets_token_balances_per_owner_and_mint() {
+        let pre = [
+            balance("alice-usdc", "alice", "usdc", 5_000_000),
+            balance("bob-usdc", "bob", "usdc", 1_000_000),
+            balance("bob-bonk", "bob", "bonk", 7),
+        ];
+        let post = [
+            balance("alice-usdc", "alice", "usdc", 3_500_000),
+            balance("bob-usdc", "bob", "usdc", 2_500_000),
+            balance("bob-bonk", "bob", "bonk", 7),
+        ];
+
+        let changes = YellowstoneClient::token_balance_changes(&pre, &post);
+        assert_eq!(
+            deltas(&changes),
+            [("alice", "usdc", -1_500_000), ("bob", "usdc", 1_500_000)]
+        );
+        assert_eq!(changes[0].ui_delta, -1.5);
+    }
+
+    #[test]
+    fn counts_created_and_closed_token_accounts_as_zero() {
+        // Alice moves her whole balance into a new token account and closes the old one.
+        let pre = [balance("alice-old", "alice", "usdc", 4_000_000)];
+        let post = [
+            balance("alice-new", "alice", "usdc", 4_000_000),
+            balance("bob-usdc", "bob", "usdc", 250_000),
+        ];
+
+        let changes = YellowstoneClient::token_balance_changes(&pre, &post);
+        assert_eq!(deltas(&changes), [("bob", "usdc", 250_000)]);
+        assert_eq!(changes[0].ui_delta, 0.25);
+
+        let changes = YellowstoneClient::token_balance_changes(&post, &[]);
+        assert_eq!(
+            deltas(&changes),
+            [("alice", "usdc", -4_000_000), ("bob", "usdc", -250_000)]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::schema::{self, Decoded, Envelope, OwnedEnvelope, SCHEMA_VERSION};
+use crate::types::IndexEvent;
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Stream entry field holding the encoded event.
+pub const PAYLOAD_FIELD: &str = "payload";
+/// Stream entry field naming how `payload` is encoded, e.g. `msgpack+zstd/1`. Entries
+/// without it predate codecs and are plain JSON.
+pub const CODEC_FIELD: &str = "codec";
+/// Version of the header format; bumped when the meaning of a header changes. Since
+/// version 2 payloads are schema [`Envelope`]s; before, they were bare events.
+pub const CODEC_VERSION: u32 = 2;
+/// Header version of bare-event payloads, including entries without a header.
+pub const LEGACY_CODEC_VERSION: u32 = 1;
+
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Compression levels zstd accepts.
+pub fn zstd_levels() -> RangeInclusive<i32> {
+    zstd::compression_level_range()
+}
+
+/// Serialization format of the event itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    /// Compact but not self-describing: producers and consumers must run the same
+    /// [`SCHEMA_VERSION`] and be upgraded in lockstep. Not for rolling upgrades.
+    Bincode,
+    /// MessagePack with field names, so unknown fields are skipped on decode.
+    MessagePack,
+}
+
+impl Format {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Bincode => "bincode",
+            Self::MessagePack => "msgpack",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "bincode" => Ok(Self::Bincode),
+            "msgpack" | "messagepack" => Ok(Self::MessagePack),
+            other => Err(anyhow!(
+                "unknown codec '{}' (expected json, bincode or msgpack)",
+                other
+            )),
+        }
+    }
+}
+
+/// How stream payloads are encoded: a format, optionally zstd-compressed. The header
+/// written next to each payload lets consumers decode entries from any producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Codec {
+    pub format: Format,
+    /// zstd level; `None` leaves payloads uncompressed.
+    pub zstd_level: Option<i32>,
+    /// Header version the payloads were written with.
+    pub version: u32,
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self {
+            format: Format::Json,
+            zstd_level: None,
+            version: CODEC_VERSION,
+        }
+    }
+}
+
+impl Codec {
+    /// The codec of an entry from its `codec` field; `None` means a legacy JSON entry.
+    pub fn from_header(header: Option<&str>) -> Result<Self> {
+        let Some(header) = header else {
+            return Ok(Self {
+                version: LEGACY_CODEC_VERSION,
+                ..Self::default()
+            });
+        };
+        let (name, version) = header
+            .split_once('/')
+            .ok_or_else(|| anyhow!("malformed codec header '{}'", header))?;
+        let version: u32 = version
+            .parse()
+            .with_context(|| format!("malformed codec header '{}'", header))?;
+        if version > CODEC_VERSION {
+            bail!(
+                "codec header '{}' is newer than supported version {}",
+                header,
+                CODEC_VERSION
+            );
+        }
+
+        let (format, compressed) = match name.split_once('+') {
+            Some((format, "zstd")) => (format, true),
+            Some((_, compression)) => bail!("unknown compression '{}'", compression),
+            None => (name, false),
+        };
+        Ok(Self {
+            format: format.parse()?,
+            // The level only matters when encoding.
+            zstd_level: compressed.then_some(DEFAULT_ZSTD_LEVEL),
+            version,
+        })
+    }
+
+    /// Header of the payloads `encode` produces.
+    pub fn header(&self) -> String {
+        Self {
+            version: CODEC_VERSION,
+            ..*self
+        }
+        .to_string()
+    }
+
+    /// Encodes `event` in an envelope of the current schema version.
+    pub fn encode(&self, event: &IndexEvent) -> Result<Vec<u8>> {
+        let envelope = Envelope::new(event);
+        let encoded = match self.format {
+            Format::Json => serde_json::to_vec(&envelope)?,
+            Format::Bincode => bincode::serialize(&envelope)?,
+            Format::MessagePack => rmp_serde::to_vec_named(&envelope)?,
+        };
+        match self.zstd_level {
+            Some(level) => Ok(zstd::encode_all(encoded.as_slice(), level)?),
+            None => Ok(encoded),
+        }
+    }
+
+    /// Decodes a payload of any schema version this build can upgrade from, except that
+    /// bincode, having no field names, only reads its own schema version.
+    pub fn decode(&self, payload: &[u8]) -> Result<Decoded> {
+        let decompressed;
+        let payload = match self.zstd_level {
+            Some(_) => {
+                decompressed = zstd::decode_all(payload)?;
+                decompressed.as_slice()
+            }
+            None => payload,
+        };
+        match self.format {
+            Format::Json => self.decode_self_describing(
+                || Ok(serde_json::from_slice(payload)?),
+                || Ok(serde_json::from_slice(payload)?),
+            ),
+            Format::MessagePack => self.decode_self_describing(
+                || Ok(rmp_serde::from_slice(payload)?),
+                || Ok(rmp_serde::from_slice(payload)?),
+            ),
+            Format::Bincode if self.version == LEGACY_CODEC_VERSION => {
+                Ok(Decoded::Event(bincode::deserialize(payload)?))
+            }
+            Format::Bincode => {
+                // The version leads the envelope. It is checked before the event, which in
+                // another schema has another layout and could decode into wrong values.
+                let schema_version: u32 = bincode::deserialize(payload)?;
+                if schema_version != SCHEMA_VERSION {
+                    bail!(
+                        "bincode payload has schema version {} but this build reads {}",
+                        schema_version,
+                        SCHEMA_VERSION
+                    );
+                }
+                let envelope: OwnedEnvelope<IndexEvent> = bincode::deserialize(payload)?;
+                Ok(Decoded::Event(envelope.event))
+            }
+        }
+    }
+
+    /// Decodes straight into the event types when the payload is in the current schema,
+    /// and through the untyped upgrade path otherwise.
+    fn decode_self_describing(
+        &self,
+        typed: impl FnOnce() -> Result<OwnedEnvelope<IndexEvent>>,
+        untyped: impl FnOnce() -> Result<Value>,
+    ) -> Result<Decoded> {
+        if self.version == LEGACY_CODEC_VERSION {
+            return schema::from_value(schema::LEGACY_SCHEMA_VERSION, untyped()?);
+        }
+        if let Ok(envelope) = typed() {
+            if envelope.schema_version >= SCHEMA_VERSION {
+                return Ok(Decoded::Event(envelope.event));
+            }
+        }
+        let envelope: OwnedEnvelope<Value> = serde_json::from_value(untyped()?)?;
+        schema::from_value(envelope.schema_version, envelope.event)
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format.as_str())?;
+        if self.zstd_level.is_some() {
+            write!(f, "+zstd")?;
+        }
+        write!(f, "/{}", self.version)
+    }
+}
+
+/// Serde adapter for base64 fields: a base64 string in human-readable formats (JSON),
+/// raw bytes in binary ones, so binary payloads do not carry base64 overhead. Untyped
+/// values may hold either form, since MessagePack bytes become arrays there.
+pub(crate) mod base64_bytes {
+    use base64::{engine::general_purpose, Engine as _};
+    use serde::de::{self, Visitor};
+    use serde::ser::Error as _;
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(value);
+        }
+        let bytes = general_purpose::STANDARD
+            .decode(value)
+            .map_err(S::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Base64Visitor)
+        } else {
+            deserializer.deserialize_byte_buf(Base64Visitor)
+        }
+    }
+
+    struct Base64Visitor;
+
+    impl<'de> Visitor<'de> for Base64Visitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a base64 string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_string<E: de::Error>(self, value: String) -> Result<String, E> {
+            Ok(value)
+        }
+
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<String, E> {
+            Ok(general_purpose::STANDARD.encode(value))
+        }
+
+        // bincode and untyped values hand byte buffers over as sequences.
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<String, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            Ok(general_purpose::STANDARD.encode(bytes))
+        }
+    }
+}
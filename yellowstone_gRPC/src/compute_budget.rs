@@ -0,0 +1,196 @@
+use crate::types::ComputeBudget;
+
+pub const COMPUTE_BUDGET_PROGRAM_ID: &str = "ComputeBudget111111111111111111111111111111";
+
+/// Programs the runtime runs natively, including ComputeBudget itself.
+pub const BUILTIN_PROGRAM_IDS: &[&str] = &[
+    "11111111111111111111111111111111",
+    "Vote111111111111111111111111111111111111111",
+    "Stake11111111111111111111111111111111111111",
+    "Config1111111111111111111111111111111111111",
+    "AddressLookupTab1e1111111111111111111111111",
+    "BPFLoader1111111111111111111111111111111111",
+    "BPFLoader2111111111111111111111111111111111",
+    "BPFLoaderUpgradeab1e11111111111111111111111",
+    "LoaderV411111111111111111111111111111111111",
+    COMPUTE_BUDGET_PROGRAM_ID,
+];
+
+/// Compute units a non-builtin instruction gets when the transaction sets no limit.
+pub const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+/// Compute units a builtin instruction gets when the transaction sets no limit.
+pub const BUILTIN_INSTRUCTION_COMPUTE_UNIT_LIMIT: u64 = 3_000;
+pub const MAX_COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+/// Applies one ComputeBudget program instruction to `budget`. Unknown or malformed
+/// instructions are ignored.
+pub fn apply_instruction(data: &[u8], budget: &mut ComputeBudget) {
+    let Some((&discriminator, args)) = data.split_first() else {
+        return;
+    };
+
+    match discriminator {
+        // RequestHeapFrame(u32)
+        1 => {
+            if let Some(bytes) = read_u32(args) {
+                budget.heap_frame_bytes = Some(bytes);
+            }
+        }
+        // SetComputeUnitLimit(u32)
+        2 => {
+            if let Some(units) = read_u32(args) {
+                budget.unit_limit = Some(units);
+            }
+        }
+        // SetComputeUnitPrice(u64), in micro-lamports per compute unit
+        3 => {
+            if let Some(price) = args.get(..8).and_then(|bytes| bytes.try_into().ok()) {
+                budget.unit_price_micro_lamports = Some(u64::from_le_bytes(price));
+            }
+        }
+        // SetLoadedAccountsDataSizeLimit(u32)
+        4 => {
+            if let Some(bytes) = read_u32(args) {
+                budget.loaded_accounts_data_size_limit = Some(bytes);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn is_builtin(program_id: &str) -> bool {
+    BUILTIN_PROGRAM_IDS.contains(&program_id)
+}
+
+/// Lamports paid on top of the signature fees: the unit price times the requested unit
+/// limit, rounded up. Without an explicit limit the runtime defaults are assumed: 3,000
+/// units per builtin instruction and 200,000 per other instruction.
+pub fn priority_fee(
+    budget: &ComputeBudget,
+    builtin_instructions: usize,
+    other_instructions: usize,
+) -> u64 {
+    let Some(price) = budget.unit_price_micro_lamports else {
+        return 0;
+    };
+    let limit = match budget.unit_limit {
+        Some(limit) => u64::from(limit),
+        None => {
+            BUILTIN_INSTRUCTION_COMPUTE_UNIT_LIMIT * builtin_instructions as u64
+                + DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT * other_instructions as u64
+        }
+    }
+    .min(MAX_COMPUTE_UNIT_LIMIT);
+
+    // Saturates: a price near `u64::MAX` would otherwise wrap to a small fee.
+    let micro_lamports = u128::from(price) * u128::from(limit);
+    u64::try_from(micro_lamports.div_ceil(MICRO_LAMPORTS_PER_LAMPORT)).unwrap_or(u64::MAX)
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    bytes
+        .get(..4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(instructions: &[Vec<u8>]) -> ComputeBudget {
+        let mut budget = ComputeBudget::default();
+        for data in instructions {
+            apply_instruction(data, &mut budget);
+        }
+        budget
+    }
+
+    fn set_unit_limit(units: u32) -> Vec<u8> {
+        [&[2][..], &units.to_le_bytes()].concat()
+    }
+
+    fn set_unit_price(micro_lamports: u64) -> Vec<u8> {
+        [&[3][..], &micro_lamports.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn parses_budget_instructions() {
+        let parsed = budget(&[
+            set_unit_limit(300_000),
+            set_unit_price(5_000),
+            [&[1][..], &65_536u32.to_le_bytes()].concat(),
+            // Truncated and unknown instructions are ignored.
+            vec![4, 0, 1],
+            vec![9, 1, 2, 3, 4],
+        ]);
+        assert_eq!(
+            parsed,
+            ComputeBudget {
+                unit_limit: Some(300_000),
+                unit_price_micro_lamports: Some(5_000),
+                heap_frame_bytes: Some(65_536),
+                loaded_accounts_data_size_limit: None,
+            }
+        );
+    }
+
+    #[test]
+    fn rounds_the_priority_fee_up() {
+        assert_eq!(priority_fee(&budget(&[set_unit_limit(300_000)]), 0, 1), 0);
+        assert_eq!(
+            priority_fee(&budget(&[set_unit_limit(1), set_unit_price(1)]), 0, 1),
+            1
+        );
+        assert_eq!(
+            priority_fee(
+                &budget(&[set_unit_limit(200_000), set_unit_price(1_000)]),
+                0,
+                1
+            ),
+            200
+        );
+        assert_eq!(
+            priority_fee(
+                &budget(&[set_unit_limit(200_001), set_unit_price(1_000)]),
+                0,
+                1
+            ),
+            201
+        );
+    }
+
+    #[test]
+    fn defaults_the_limit_per_instruction() {
+        let budget = budget(&[set_unit_price(1_000_000)]);
+        assert_eq!(priority_fee(&budget, 0, 0), 0);
+        assert_eq!(priority_fee(&budget, 0, 1), 200_000);
+        assert_eq!(priority_fee(&budget, 0, 3), 600_000);
+        // A transfer with its unit price instruction.
+        assert_eq!(priority_fee(&budget, 2, 0), 6_000);
+        assert_eq!(priority_fee(&budget, 2, 1), 206_000);
+    }
+
+    #[test]
+    fn recognizes_builtins() {
+        assert!(is_builtin("11111111111111111111111111111111"));
+        assert!(is_builtin("Vote111111111111111111111111111111111111111"));
+        assert!(is_builtin(COMPUTE_BUDGET_PROGRAM_ID));
+        assert!(!is_builtin("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"));
+    }
+
+    #[test]
+    fn caps_the_limit() {
+        let defaulted = budget(&[set_unit_price(1_000_000)]);
+        assert_eq!(priority_fee(&defaulted, 0, 8), 1_400_000);
+
+        let explicit = budget(&[set_unit_limit(u32::MAX), set_unit_price(1_000_000)]);
+        assert_eq!(priority_fee(&explicit, 0, 1), 1_400_000);
+
+        // The fee saturates rather than wrapping.
+        let overpriced = budget(&[set_unit_limit(u32::MAX), set_unit_price(u64::MAX)]);
+        assert_eq!(priority_fee(&overpriced, 0, 1), u64::MAX);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::subscription_spec::validate_pubkey;
+use crate::subscriptions::TransactionMode;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fmt;
+use tokio::sync::oneshot;
+use yellowstone_grpc_proto::geyser::{
+    SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestFilterTransactions,
+};
+
+/// Filter names the control channel manages for programs and wallets added at runtime.
+pub const TRACKED_PROGRAMS_FILTER: &str = "tracked_programs";
+pub const TRACKED_WALLETS_FILTER: &str = "tracked_wallets";
+
+/// A change to the live subscription, applied to the current `SubscribeRequest` and
+/// pushed through the open subscribe sink without restarting the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionCommand {
+    AddProgram(String),
+    RemoveProgram(String),
+    AddWallet(String),
+    RemoveWallet(String),
+    Show,
+}
+
+/// A command together with the channel its outcome is reported on.
+pub struct ControlMessage {
+    pub command: SubscriptionCommand,
+    pub reply: oneshot::Sender<Result<String, String>>,
+}
+
+impl SubscriptionCommand {
+    /// Parses a single line of the admin protocol, e.g. `add-program <pubkey>`.
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut parts = line.split_whitespace();
+        let verb = parts.next().unwrap_or_default();
+        let argument = parts.next();
+        if parts.next().is_some() {
+            bail!("expected at most one argument");
+        }
+
+        let pubkey = |argument: Option<&str>| -> Result<String> {
+            let Some(pubkey) = argument else {
+                bail!("{} requires a pubkey", verb);
+            };
+            validate_pubkey(pubkey)?;
+            Ok(pubkey.to_string())
+        };
+
+        match verb {
+            "add-program" => Ok(Self::AddProgram(pubkey(argument)?)),
+            "remove-program" => Ok(Self::RemoveProgram(pubkey(argument)?)),
+            "add-wallet" => Ok(Self::AddWallet(pubkey(argument)?)),
+            "remove-wallet" => Ok(Self::RemoveWallet(pubkey(argument)?)),
+            "show" => Ok(Self::Show),
+            "" => bail!("empty command"),
+            other => bail!(
+                "unknown command '{}' (expected add-program, remove-program, add-wallet, remove-wallet or show)",
+                other
+            ),
+        }
+    }
+
+    /// Merges the command into `request`, whose filters are laid out for `mode`: in
+    /// status mode transaction filters live in `transactions_status` and there are no
+    /// account filters. Returns whether the request changed and therefore has to be
+    /// re-sent to the server.
+    pub fn apply(&self, request: &mut SubscribeRequest, mode: TransactionMode) -> bool {
+        let (accounts, transactions) = match mode {
+            TransactionMode::Full => (Some(&mut request.accounts), &mut request.transactions),
+            TransactionMode::Status => (None, &mut request.transactions_status),
+        };
+        match self {
+            Self::AddProgram(program) => {
+                let added_owner = accounts.is_some_and(|accounts| {
+                    let filter = accounts
+                        .entry(TRACKED_PROGRAMS_FILTER.to_string())
+                        .or_default();
+                    push_unique(&mut filter.owner, program)
+                });
+                let filter = transactions
+                    .entry(TRACKED_PROGRAMS_FILTER.to_string())
+                    .or_insert_with(tracked_transactions_filter);
+                let added_include = push_unique(&mut filter.account_include, program);
+                added_owner || added_include
+            }
+            Self::AddWallet(wallet) => {
+                let added_account = accounts.is_some_and(|accounts| {
+                    let filter = accounts
+                        .entry(TRACKED_WALLETS_FILTER.to_string())
+                        .or_default();
+                    push_unique(&mut filter.account, wallet)
+                });
+                let filter = transactions
+                    .entry(TRACKED_WALLETS_FILTER.to_string())
+                    .or_insert_with(tracked_transactions_filter);
+                let added_include = push_unique(&mut filter.account_include, wallet);
+                added_account || added_include
+            }
+            Self::RemoveProgram(program) => {
+                let removed_owner = accounts.is_some_and(|accounts| {
+                    remove_tracked(accounts, TRACKED_PROGRAMS_FILTER, |filter| {
+                        remove_all(&mut filter.owner, program)
+                    })
+                });
+                let removed_include =
+                    remove_tracked(transactions, TRACKED_PROGRAMS_FILTER, |filter| {
+                        remove_all(&mut filter.account_include, program)
+                    });
+                removed_owner || removed_include
+            }
+            Self::RemoveWallet(wallet) => {
+                let removed_account = accounts.is_some_and(|accounts| {
+                    remove_tracked(accounts, TRACKED_WALLETS_FILTER, |filter| {
+                        remove_all(&mut filter.account, wallet)
+                    })
+                });
+                let removed_include =
+                    remove_tracked(transactions, TRACKED_WALLETS_FILTER, |filter| {
+                        remove_all(&mut filter.account_include, wallet)
+                    });
+                removed_account || removed_include
+            }
+            Self::Show => false,
+        }
+    }
+}
+
+impl fmt::Display for SubscriptionCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddProgram(pubkey) => write!(f, "add-program {}", pubkey),
+            Self::RemoveProgram(pubkey) => write!(f, "remove-program {}", pubkey),
+            Self::AddWallet(pubkey) => write!(f, "add-wallet {}", pubkey),
+            Self::RemoveWallet(pubkey) => write!(f, "remove-wallet {}", pubkey),
+            Self::Show => write!(f, "show"),
+        }
+    }
+}
+
+/// One-line summary of the filters in `request`, used as the reply to `show`.
+pub fn describe_request(request: &SubscribeRequest) -> String {
+    let mut accounts: Vec<String> = request
+        .accounts
+        .iter()
+        .map(|(name, filter)| {
+            format!(
+                "{}(owner={:?}, account={:?})",
+                name, filter.owner, filter.account
+            )
+        })
+        .collect();
+    accounts.sort();
+    let describe_transactions = |filters: &HashMap<String, SubscribeRequestFilterTransactions>| {
+        let mut filters: Vec<String> = filters
+            .iter()
+            .map(|(name, filter)| format!("{}(account_include={:?})", name, filter.account_include))
+            .collect();
+        filters.sort();
+        filters.join(", ")
+    };
+
+    format!(
+        "accounts=[{}] transactions=[{}] transactions_status=[{}]",
+        accounts.join(", "),
+        describe_transactions(&request.transactions),
+        describe_transactions(&request.transactions_status)
+    )
+}
+
+fn tracked_transactions_filter() -> SubscribeRequestFilterTransactions {
+    SubscribeRequestFilterTransactions {
+        vote: Some(false),
+        ..Default::default()
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: &str) -> bool {
+    if list.iter().any(|existing| existing == value) {
+        return false;
+    }
+    list.push(value.to_string());
+    true
+}
+
+fn remove_all(list: &mut Vec<String>, value: &str) -> bool {
+    let before = list.len();
+    list.retain(|existing| existing != value);
+    list.len() != before
+}
+
+/// Applies `remove` to the tracked filter `name` and returns whether it removed anything.
+/// Filters from the subscription spec are left alone. A tracked filter emptied by the
+/// removal is dropped, since an empty Geyser filter matches everything.
+fn remove_tracked<F: EmptyFilter>(
+    filters: &mut HashMap<String, F>,
+    name: &str,
+    remove: impl FnOnce(&mut F) -> bool,
+) -> bool {
+    let Some(filter) = filters.get_mut(name) else {
+        return false;
+    };
+    if !remove(filter) {
+        return false;
+    }
+    if filter.is_empty() {
+        filters.remove(name);
+    }
+    true
+}
+
+trait EmptyFilter {
+    fn is_empty(&self) -> bool;
+}
+
+/// Data filters (datasize, memcmp) alone still match every account of that shape, so a
+/// filter without owners or accounts counts as empty.
+impl EmptyFilter for SubscribeRequestFilterAccounts {
+    fn is_empty(&self) -> bool {
+        self.account.is_empty() && self.owner.is_empty()
+    }
+}
+
+impl EmptyFilter for SubscribeRequestFilterTransactions {
+    fn is_empty(&self) -> bool {
+        self.account_include.is_empty()
+            && self.account_required.is_empty()
+            && self.signature.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAYDIUM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+    const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+    fn tracked_programs(request: &SubscribeRequest) -> Option<&Vec<String>> {
+        request
+            .transactions_status
+            .get(TRACKED_PROGRAMS_FILTER)
+            .map(|filter| &filter.account_include)
+    }
+
+    #[test]
+    fn edits_status_filters_in_status_mode() {
+        let mode = TransactionMode::Status;
+        let mut request = SubscribeRequest::default();
+
+        assert!(SubscriptionCommand::AddProgram(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert!(SubscriptionCommand::AddProgram(JUPITER.to_string()).apply(&mut request, mode));
+        assert!(!SubscriptionCommand::AddProgram(JUPITER.to_string()).apply(&mut request, mode));
+        assert_eq!(
+            tracked_programs(&request),
+            Some(&vec![RAYDIUM.to_string(), JUPITER.to_string()])
+        );
+        assert!(request.transactions.is_empty());
+        assert!(request.accounts.is_empty());
+        assert!(describe_request(&request).contains(&format!(
+            "transactions_status=[tracked_programs(account_include=[\"{}\", \"{}\"])]",
+            RAYDIUM, JUPITER
+        )));
+
+        assert!(SubscriptionCommand::RemoveProgram(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert_eq!(tracked_programs(&request), Some(&vec![JUPITER.to_string()]));
+        assert!(SubscriptionCommand::RemoveProgram(JUPITER.to_string()).apply(&mut request, mode));
+        assert_eq!(tracked_programs(&request), None);
+        assert!(!SubscriptionCommand::RemoveProgram(JUPITER.to_string()).apply(&mut request, mode));
+    }
+
+    #[test]
+    fn edits_account_and_transaction_filters_in_full_mode() {
+        let mode = TransactionMode::Full;
+        let mut request = SubscribeRequest::default();
+
+        assert!(SubscriptionCommand::AddWallet(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert_eq!(request.accounts[TRACKED_WALLETS_FILTER].account, [RAYDIUM]);
+        assert_eq!(
+            request.transactions[TRACKED_WALLETS_FILTER].account_include,
+            [RAYDIUM]
+        );
+        assert!(request.transactions_status.is_empty());
+
+        assert!(SubscriptionCommand::RemoveWallet(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert!(request.accounts.is_empty());
+        assert!(request.transactions.is_empty());
+    }
+
+    #[test]
+    fn leaves_spec_filters_alone_on_remove() {
+        let mode = TransactionMode::Full;
+        let mut request = SubscribeRequest::default();
+        request.accounts.insert(
+            "raydium_pools".to_string(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![RAYDIUM.to_string()],
+                ..Default::default()
+            },
+        );
+        request.transactions.insert(
+            "raydium".to_string(),
+            SubscribeRequestFilterTransactions {
+                account_include: vec![RAYDIUM.to_string()],
+                ..Default::default()
+            },
+        );
+        let spec = request.clone();
+
+        assert!(!SubscriptionCommand::RemoveProgram(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert_eq!(request, spec);
+
+        assert!(SubscriptionCommand::AddProgram(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert!(SubscriptionCommand::RemoveProgram(RAYDIUM.to_string()).apply(&mut request, mode));
+        assert_eq!(request, spec);
+    }
+}
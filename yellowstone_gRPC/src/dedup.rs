@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, HashSet};
+use yellowstone_grpc_proto::geyser::{subscribe_update, SubscribeUpdate};
+
+/// Number of slots behind the newest one for which seen keys are remembered.
+pub const DEFAULT_DEDUP_SLOT_WINDOW: u64 = 150;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum DedupKey {
+    Transaction(Vec<u8>),
+    Account(Vec<u8>, u64),
+    Slot(i32),
+    Block,
+    BlockMeta,
+    Entry(u64),
+    TransactionStatus(Vec<u8>),
+}
+
+/// Drops updates that were already emitted, so racing several providers (or replaying
+/// a slot after reconnect) does not publish the same transaction or account write twice.
+///
+/// Transactions and transaction statuses are keyed by signature, accounts by
+/// `(pubkey, write_version)`, slot updates by status and entries by index; blocks and
+/// block metas are unique per slot. The transactions and accounts inside a block share
+/// the keys of their standalone updates.
+/// Keys are bucketed per slot and forgotten once the slot falls out of the window.
+pub struct Deduplicator {
+    window: u64,
+    seen: BTreeMap<u64, HashSet<DedupKey>>,
+}
+
+impl Deduplicator {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            seen: BTreeMap::new(),
+        }
+    }
+
+    /// Returns `true` the first time an update is seen. Updates without a dedup key
+    /// (pings, pongs) always pass.
+    pub fn first_seen(&mut self, update: &SubscribeUpdate) -> bool {
+        let Some((slot, key)) = Self::key(update) else {
+            return true;
+        };
+
+        if self.is_too_old(slot) {
+            // Too old to tell; let it through rather than risk dropping data.
+            return true;
+        }
+
+        let inserted = self.seen.entry(slot).or_default().insert(key);
+        self.evict();
+        inserted
+    }
+
+    /// Drops the transactions and account writes of a block update that were already
+    /// emitted as standalone updates, and records the rest under the same keys, so a
+    /// standalone update arriving after the block is dropped as well.
+    pub fn strip_seen(&mut self, update: &mut SubscribeUpdate) {
+        let Some(subscribe_update::UpdateOneof::Block(block)) = update.update_oneof.as_mut() else {
+            return;
+        };
+        if self.is_too_old(block.slot) {
+            return;
+        }
+
+        let seen = self.seen.entry(block.slot).or_default();
+        block.transactions.retain(|transaction| {
+            seen.insert(DedupKey::Transaction(transaction.signature.clone()))
+        });
+        block.accounts.retain(|account| {
+            seen.insert(DedupKey::Account(
+                account.pubkey.clone(),
+                account.write_version,
+            ))
+        });
+        self.evict();
+    }
+
+    fn is_too_old(&self, slot: u64) -> bool {
+        self.seen
+            .last_key_value()
+            .is_some_and(|(&newest, _)| slot + self.window < newest)
+    }
+
+    fn evict(&mut self) {
+        let Some((&newest, _)) = self.seen.last_key_value() else {
+            return;
+        };
+        let cutoff = newest.saturating_sub(self.window);
+        while let Some((&oldest, _)) = self.seen.first_key_value() {
+            if oldest >= cutoff {
+                break;
+            }
+            self.seen.pop_first();
+        }
+    }
+
+    fn key(update: &SubscribeUpdate) -> Option<(u64, DedupKey)> {
+        match update.update_oneof.as_ref()? {
+            subscribe_update::UpdateOneof::Transaction(transaction) => {
+                let info = transaction.transaction.as_ref()?;
+                Some((
+                    transaction.slot,
+                    DedupKey::Transaction(info.signature.clone()),
+                ))
+            }
+            subscribe_update::UpdateOneof::Account(account) => {
+                let info = account.account.as_ref()?;
+                Some((
+                    account.slot,
+                    DedupKey::Account(info.pubkey.clone(), info.write_version),
+                ))
+            }
+            subscribe_update::UpdateOneof::Slot(slot) => {
+                Some((slot.slot, DedupKey::Slot(slot.status)))
+            }
+            subscribe_update::UpdateOneof::Block(block) => Some((block.slot, DedupKey::Block)),
+            subscribe_update::UpdateOneof::BlockMeta(block_meta) => {
+                Some((block_meta.slot, DedupKey::BlockMeta))
+            }
+            subscribe_update::UpdateOneof::Entry(entry) => {
+                Some((entry.slot, DedupKey::Entry(entry.index)))
+            }
+            subscribe_update::UpdateOneof::TransactionStatus(status) => Some((
+                status.slot,
+                DedupKey::TransactionStatus(status.signature.clone()),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yellowstone_grpc_proto::geyser::{
+        SubscribeUpdateAccount, SubscribeUpdateAccountInfo, SubscribeUpdateBlock,
+        SubscribeUpdatePing, SubscribeUpdateSlot, SubscribeUpdateTransaction,
+        SubscribeUpdateTransactionInfo,
+    };
+
+    fn update(update: subscribe_update::UpdateOneof) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(update),
+            ..Default::default()
+        }
+    }
+
+    fn account(slot: u64, pubkey: u8, write_version: u64) -> SubscribeUpdate {
+        update(subscribe_update::UpdateOneof::Account(
+            SubscribeUpdateAccount {
+                account: Some(SubscribeUpdateAccountInfo {
+                    pubkey: vec![pubkey; 32],
+                    write_version,
+                    ..Default::default()
+                }),
+                slot,
+                is_startup: false,
+            },
+        ))
+    }
+
+    fn slot(slot: u64, status: i32) -> SubscribeUpdate {
+        update(subscribe_update::UpdateOneof::Slot(SubscribeUpdateSlot {
+            slot,
+            status,
+            ..Default::default()
+        }))
+    }
+
+    fn transaction_info(signature: u8) -> SubscribeUpdateTransactionInfo {
+        SubscribeUpdateTransactionInfo {
+            signature: vec![signature; 64],
+            ..Default::default()
+        }
+    }
+
+    fn transaction(slot: u64, signature: u8) -> SubscribeUpdate {
+        update(subscribe_update::UpdateOneof::Transaction(
+            SubscribeUpdateTransaction {
+                transaction: Some(transaction_info(signature)),
+                slot,
+            },
+        ))
+    }
+
+    fn block(slot: u64, signatures: &[u8]) -> SubscribeUpdate {
+        update(subscribe_update::UpdateOneof::Block(SubscribeUpdateBlock {
+            slot,
+            transactions: signatures.iter().copied().map(transaction_info).collect(),
+            ..Default::default()
+        }))
+    }
+
+    fn block_signatures(update: &SubscribeUpdate) -> Vec<u8> {
+        let Some(subscribe_update::UpdateOneof::Block(block)) = &update.update_oneof else {
+            panic!("expected a block update");
+        };
+        block
+            .transactions
+            .iter()
+            .map(|transaction| transaction.signature[0])
+            .collect()
+    }
+
+    #[test]
+    fn drops_repeated_updates() {
+        let mut dedup = Deduplicator::new(DEFAULT_DEDUP_SLOT_WINDOW);
+        assert!(dedup.first_seen(&transaction(10, 1)));
+        assert!(!dedup.first_seen(&transaction(10, 1)));
+        assert!(dedup.first_seen(&transaction(10, 2)));
+
+        assert!(dedup.first_seen(&account(10, 1, 5)));
+        assert!(!dedup.first_seen(&account(10, 1, 5)));
+        assert!(dedup.first_seen(&account(10, 1, 6)));
+
+        assert!(dedup.first_seen(&slot(10, 0)));
+        assert!(!dedup.first_seen(&slot(10, 0)));
+        assert!(dedup.first_seen(&slot(10, 1)));
+
+        let ping = update(subscribe_update::UpdateOneof::Ping(SubscribeUpdatePing {}));
+        assert!(dedup.first_seen(&ping));
+        assert!(dedup.first_seen(&ping));
+    }
+
+    #[test]
+    fn forgets_slots_that_leave_the_window() {
+        let mut dedup = Deduplicator::new(2);
+        assert!(dedup.first_seen(&transaction(10, 1)));
+        assert!(dedup.first_seen(&transaction(11, 2)));
+        assert!(dedup.first_seen(&transaction(12, 3)));
+        assert!(!dedup.first_seen(&transaction(10, 1)));
+
+        // Slot 13 pushes slot 10 out; its updates are let through rather than dropped.
+        assert!(dedup.first_seen(&transaction(13, 4)));
+        assert!(dedup.first_seen(&transaction(10, 1)));
+        assert!(dedup.first_seen(&transaction(10, 1)));
+        assert!(!dedup.first_seen(&transaction(11, 2)));
+        assert_eq!(dedup.seen.len(), 3);
+    }
+
+    #[test]
+    fn strips_block_transactions_seen_on_their_own() {
+        let mut dedup = Deduplicator::new(DEFAULT_DEDUP_SLOT_WINDOW);
+        assert!(dedup.first_seen(&transaction(10, 1)));
+
+        let mut update = block(10, &[1, 2]);
+        assert!(dedup.first_seen(&update));
+        dedup.strip_seen(&mut update);
+        assert_eq!(block_signatures(&update), [2]);
+
+        // The standalone update of a transaction the block carried comes too late.
+        assert!(!dedup.first_seen(&transaction(10, 2)));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::types::BlockEntry;
+use std::collections::BTreeMap;
+
+/// Holds entry updates per slot until the slot's block meta arrives and they can be
+/// attached to its block event. Slots more than `max_slots` behind the newest entry are
+/// dropped, since their block meta is not coming.
+pub struct EntryBuffer {
+    max_slots: u64,
+    entries: BTreeMap<u64, Vec<BlockEntry>>,
+}
+
+impl EntryBuffer {
+    pub fn new(max_slots: u64) -> Self {
+        Self {
+            max_slots,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, slot: u64, entry: BlockEntry) {
+        self.entries.entry(slot).or_default().push(entry);
+
+        if let Some((&newest, _)) = self.entries.last_key_value() {
+            let cutoff = newest.saturating_sub(self.max_slots);
+            self.entries = self.entries.split_off(&cutoff);
+        }
+    }
+
+    /// Removes and returns the entries of `slot` in index order, or `None` if no entry
+    /// was received for it (e.g. the subscription has no entry filter).
+    pub fn take(&mut self, slot: u64) -> Option<Vec<BlockEntry>> {
+        let mut entries = self.entries.remove(&slot)?;
+        entries.sort_by_key(|entry| entry.index);
+        Some(entries)
+    }
+}
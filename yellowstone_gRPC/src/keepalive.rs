@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeRequestPing};
+
+/// How often the engine pings the server and how long it waits for the matching pong
+/// before declaring the connection dead.
+#[derive(Debug, Clone, Copy)]
+pub struct KeepalivePolicy {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for KeepalivePolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Outcome of a keepalive tick.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PingAction {
+    /// Send a client ping with this id.
+    Send(i32),
+    /// The oldest outstanding ping has not been answered within the timeout.
+    TimedOut { id: i32, elapsed: Duration },
+}
+
+/// Tracks client pings in flight for one session and the measured round-trip time.
+#[derive(Debug)]
+pub struct PingTracker {
+    policy: KeepalivePolicy,
+    next_id: i32,
+    outstanding: Option<(i32, Instant)>,
+    last_rtt: Option<Duration>,
+}
+
+impl PingTracker {
+    pub fn new(policy: KeepalivePolicy) -> Self {
+        Self {
+            policy,
+            next_id: 1,
+            outstanding: None,
+            last_rtt: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.policy.interval
+    }
+
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.last_rtt
+    }
+
+    /// Called on every keepalive tick. Only one ping is kept in flight; while it is
+    /// unanswered no new ping is sent, and once it exceeds the timeout the session is dead.
+    pub fn tick(&mut self, now: Instant) -> Option<PingAction> {
+        if let Some((id, sent_at)) = self.outstanding {
+            let elapsed = now.duration_since(sent_at);
+            if elapsed >= self.policy.timeout {
+                return Some(PingAction::TimedOut { id, elapsed });
+            }
+            return None;
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.outstanding = Some((id, now));
+        Some(PingAction::Send(id))
+    }
+
+    /// Restarts the timeout of the ping in flight, while its pong cannot be read because
+    /// the stream is stalled on a full updates channel.
+    pub fn restart_deadline(&mut self, now: Instant) {
+        if let Some((_, sent_at)) = self.outstanding.as_mut() {
+            *sent_at = now;
+        }
+    }
+
+    /// Records a pong and returns the round-trip time if it answers the ping in flight.
+    pub fn on_pong(&mut self, id: i32, now: Instant) -> Option<Duration> {
+        match self.outstanding {
+            Some((outstanding_id, sent_at)) if outstanding_id == id => {
+                let rtt = now.duration_since(sent_at);
+                self.outstanding = None;
+                self.last_rtt = Some(rtt);
+                Some(rtt)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A ping-only request. Yellowstone answers it with a pong and leaves the active
+/// filters untouched, so it is safe to send on the live subscribe sink.
+pub fn ping_request(id: i32) -> SubscribeRequest {
+    SubscribeRequest {
+        ping: Some(SubscribeRequestPing { id }),
+        ..Default::default()
+    }
+}
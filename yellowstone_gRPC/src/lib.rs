@@ -1,4 +1,40 @@
+// The lint reports the `yellowstone_gRPC` crate name at the crate root, so it can only be
+// allowed there; every module turns it back on.
+#![allow(non_snake_case)]
+
+#[warn(non_snake_case)]
+pub mod backpressure;
+#[warn(non_snake_case)]
+pub mod block_time;
+#[warn(non_snake_case)]
 pub mod client;
+#[warn(non_snake_case)]
+pub mod codec;
+#[warn(non_snake_case)]
+pub mod compute_budget;
+#[warn(non_snake_case)]
+pub mod control;
+#[warn(non_snake_case)]
+pub mod dedup;
+#[warn(non_snake_case)]
+pub mod entries;
+#[warn(non_snake_case)]
+pub mod keepalive;
+#[warn(non_snake_case)]
+pub mod preety_print;
+#[warn(non_snake_case)]
+pub mod publisher;
+#[warn(non_snake_case)]
+pub mod reconnect;
+#[warn(non_snake_case)]
+pub mod retention;
+#[warn(non_snake_case)]
+pub mod schema;
+#[warn(non_snake_case)]
+pub mod session;
+#[warn(non_snake_case)]
+pub mod subscription_spec;
+#[warn(non_snake_case)]
 pub mod subscriptions;
+#[warn(non_snake_case)]
 pub mod types;
-pub mod preety_print;
\ No newline at end of file
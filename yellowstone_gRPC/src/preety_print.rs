@@ -1,4 +1,8 @@
-use crate::types::{SolanaAccount, SolanaTransaction, TransactionInstruction};
+use crate::types::{
+    InnerInstruction, SlotStatus, SlotUpdate, SolanaAccount, SolanaBlock, SolanaTransaction,
+    TokenBalance, TokenBalanceChange, TransactionErrorInfo, TransactionInstruction,
+    TransactionStatus,
+};
 use std::fmt;
 
 impl fmt::Display for TransactionInstruction {
@@ -15,6 +19,58 @@ impl fmt::Display for TransactionInstruction {
     }
 }
 
+impl fmt::Display for InnerInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    InnerInstruction:")?;
+        writeln!(f, "      parent_index: {}", self.parent_index)?;
+        writeln!(f, "      stack_height: {:?}", self.stack_height)?;
+        writeln!(f, "      program_id: {}", self.program_id)?;
+        writeln!(f, "      accounts: [")?;
+        for acc in &self.accounts {
+            writeln!(f, "        \"{}\",", acc)?;
+        }
+        writeln!(f, "      ]")?;
+        writeln!(f, "      data: {}", self.data)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for TokenBalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "    {} (index {}): {} of {} ({} decimals) [owner {}, program {}]",
+            self.account,
+            self.account_index,
+            self.ui_amount,
+            self.mint,
+            self.decimals,
+            self.owner,
+            self.program_id
+        )
+    }
+}
+
+impl fmt::Display for TokenBalanceChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "    {}: {:+} of {} (raw {:+})",
+            self.owner, self.ui_delta, self.mint, self.delta
+        )
+    }
+}
+
+impl fmt::Display for TransactionErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.kind)?;
+        if let Some(code) = self.custom_code {
+            write!(f, " [custom code {}]", code)?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for SolanaTransaction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "SolanaTransaction:")?;
@@ -23,7 +79,14 @@ impl fmt::Display for SolanaTransaction {
         writeln!(f, "  is_vote: {}", self.is_vote)?;
         writeln!(f, "  index: {}", self.index)?;
         writeln!(f, "  success: {}", self.success)?;
+        if let Some(error) = &self.error {
+            writeln!(f, "  error: {}", error)?;
+        }
         writeln!(f, "  fee: {:?}", self.fee)?;
+        writeln!(f, "  priority_fee: {:?}", self.priority_fee)?;
+        writeln!(f, "  base_fee: {:?}", self.base_fee)?;
+        writeln!(f, "  cost_units: {:?}", self.cost_units)?;
+        writeln!(f, "  compute_budget: {:?}", self.compute_budget)?;
         writeln!(
             f,
             "  compute_units_consumed: {:?}",
@@ -36,11 +99,20 @@ impl fmt::Display for SolanaTransaction {
         }
         writeln!(f, "  ]")?;
 
+        writeln!(f, "  inner_instructions: [")?;
+        for instr in &self.inner_instructions {
+            writeln!(f, "{}", instr)?;
+        }
+        writeln!(f, "  ]")?;
+
         writeln!(f, "  account_keys: [")?;
         for key in &self.account_keys {
             writeln!(f, "    \"{}\",", key)?;
         }
         writeln!(f, "  ]")?;
+        writeln!(f, "  is_writable: {:?}", self.is_writable)?;
+        writeln!(f, "  is_signer: {:?}", self.is_signer)?;
+        writeln!(f, "  version: {:?}", self.version)?;
 
         writeln!(f, "  log_messages: [")?;
         for log in &self.log_messages {
@@ -50,7 +122,45 @@ impl fmt::Display for SolanaTransaction {
 
         writeln!(f, "  pre_balances: {:?}", self.pre_balances)?;
         writeln!(f, "  post_balances: {:?}", self.post_balances)?;
+
+        writeln!(f, "  pre_token_balances: [")?;
+        for balance in &self.pre_token_balances {
+            writeln!(f, "{}", balance)?;
+        }
+        writeln!(f, "  ]")?;
+
+        writeln!(f, "  post_token_balances: [")?;
+        for balance in &self.post_token_balances {
+            writeln!(f, "{}", balance)?;
+        }
+        writeln!(f, "  ]")?;
+
+        writeln!(f, "  token_balance_changes: [")?;
+        for change in &self.token_balance_changes {
+            writeln!(f, "{}", change)?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "  rewards: [")?;
+        for reward in &self.rewards {
+            writeln!(
+                f,
+                "    {}: {} lamports ({})",
+                reward.pubkey,
+                reward.lamports,
+                reward.reward_type.as_deref().unwrap_or("unknown")
+            )?;
+        }
+        writeln!(f, "  ]")?;
+
+        if let Some(return_data) = &self.return_data {
+            writeln!(
+                f,
+                "  return_data: {} from {}",
+                return_data.data, return_data.program_id
+            )?;
+        }
         writeln!(f, "  timestamp: {}", self.timestamp)?;
+        writeln!(f, "  ingested_at: {}", self.ingested_at)?;
         Ok(())
     }
 }
@@ -72,6 +182,88 @@ impl fmt::Display for SolanaAccount {
             self.txn_signature.as_deref().unwrap_or("None")
         )?;
         writeln!(f, "  timestamp: {}", self.timestamp)?;
+        writeln!(f, "  ingested_at: {}", self.ingested_at)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for SlotStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Display for SlotUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "SlotUpdate:")?;
+        writeln!(f, "  slot: {}", self.slot)?;
+        writeln!(f, "  parent: {:?}", self.parent)?;
+        writeln!(f, "  status: {}", self.status)?;
+        writeln!(
+            f,
+            "  dead_error: {}",
+            self.dead_error.as_deref().unwrap_or("None")
+        )?;
+        writeln!(f, "  timestamp: {}", self.timestamp)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for SolanaBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "SolanaBlock:")?;
+        writeln!(f, "  slot: {}", self.slot)?;
+        writeln!(f, "  blockhash: {}", self.blockhash)?;
+        writeln!(f, "  parent_slot: {}", self.parent_slot)?;
+        writeln!(f, "  parent_blockhash: {}", self.parent_blockhash)?;
+        writeln!(f, "  block_height: {:?}", self.block_height)?;
+        writeln!(f, "  block_time: {:?}", self.block_time)?;
+        writeln!(f, "  rewards: {}", self.rewards.len())?;
+        writeln!(
+            f,
+            "  executed_transaction_count: {}",
+            self.executed_transaction_count
+        )?;
+        writeln!(f, "  entries_count: {}", self.entries_count)?;
+        if let Some(entries) = &self.entries {
+            writeln!(f, "  entries: [")?;
+            for entry in entries {
+                writeln!(
+                    f,
+                    "    #{} {} ({} hashes, {} transactions from {})",
+                    entry.index,
+                    entry.hash,
+                    entry.num_hashes,
+                    entry.executed_transaction_count,
+                    entry.starting_transaction_index
+                )?;
+            }
+            writeln!(f, "  ]")?;
+        }
+        writeln!(
+            f,
+            "  updated_account_count: {:?}",
+            self.updated_account_count
+        )?;
+        writeln!(f, "  timestamp: {}", self.timestamp)?;
+        writeln!(f, "  ingested_at: {}", self.ingested_at)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TransactionStatus:")?;
+        writeln!(f, "  signature: {}", self.signature)?;
+        writeln!(f, "  slot: {}", self.slot)?;
+        writeln!(f, "  index: {}", self.index)?;
+        writeln!(f, "  is_vote: {}", self.is_vote)?;
+        writeln!(f, "  success: {}", self.success)?;
+        if let Some(error) = &self.error {
+            writeln!(f, "  error: {}", error)?;
+        }
+        writeln!(f, "  timestamp: {}", self.timestamp)?;
+        writeln!(f, "  ingested_at: {}", self.ingested_at)?;
         Ok(())
     }
 }
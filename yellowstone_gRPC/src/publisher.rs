@@ -0,0 +1,317 @@
+use crate::backpressure::{BackpressureLevel, BackpressurePolicy};
+use crate::codec::{Codec, CODEC_FIELD, PAYLOAD_FIELD};
+use crate::retention::{LagPolicy, RetentionPolicy, SpillFile, StreamStatus};
+use crate::types::IndexEvent;
+use anyhow::{anyhow, Context, Result};
+use redis::aio::MultiplexedConnection;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Most pipelines of spilled events replayed per lag check, so a long replay does not
+/// stall publishing of the current slot.
+const REPLAY_STEP_BATCHES: usize = 20;
+
+/// Redis key holding the newest processed slot.
+pub const CURRENT_SLOT_KEY: &str = "current_slot";
+
+/// Size of the queue between update decoding and the publisher task, how many writes
+/// go into one pipeline, how often queue metrics are logged and how events are encoded.
+#[derive(Debug, Clone, Copy)]
+pub struct PublishPolicy {
+    pub queue_capacity: usize,
+    pub max_batch: usize,
+    pub metrics_interval: Duration,
+    pub codec: Codec,
+}
+
+impl Default for PublishPolicy {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 10_000,
+            max_batch: 500,
+            metrics_interval: Duration::from_secs(10),
+            codec: Codec::default(),
+        }
+    }
+}
+
+/// A write waiting in the publisher queue.
+#[derive(Debug)]
+enum Publish {
+    /// `IndexEvent` encoded with the publisher's codec, to append to the stream.
+    Event(Vec<u8>),
+    /// Newest processed slot, stored under [`CURRENT_SLOT_KEY`].
+    CurrentSlot(u64),
+}
+
+/// Handle to the publisher task. Writes are queued in order on a bounded channel, so a
+/// slow Redis makes `publish` wait instead of buffering without limit. The handle also
+/// carries the backpressure level derived from the consumer groups' backlog.
+#[derive(Clone)]
+pub struct Publisher {
+    queue: mpsc::Sender<Publish>,
+    codec: Codec,
+    backpressure: Arc<BackpressurePolicy>,
+    level: watch::Receiver<BackpressureLevel>,
+    shed: Arc<AtomicU64>,
+}
+
+impl Publisher {
+    /// Starts the publisher task on `connection`, trimming the stream and reacting to
+    /// consumer lag as `retention` and `backpressure` say. The task ends with an error
+    /// when a pipeline fails, and with `Ok` once every handle has been dropped and the
+    /// queue is drained.
+    pub async fn spawn(
+        connection: MultiplexedConnection,
+        stream_name: &str,
+        policy: PublishPolicy,
+        retention: RetentionPolicy,
+        backpressure: BackpressurePolicy,
+    ) -> Result<(Self, JoinHandle<Result<()>>)> {
+        let spill = match retention.lag_policy {
+            LagPolicy::Spill => Some(SpillFile::open(&retention.spill_path).await?),
+            LagPolicy::Alert | LagPolicy::Pause => None,
+        };
+        let (queue, receiver) = mpsc::channel(policy.queue_capacity);
+        let (level_tx, level) = watch::channel(BackpressureLevel::None);
+        let backpressure = Arc::new(backpressure);
+        let shed = Arc::new(AtomicU64::new(0));
+        let task = PublisherTask {
+            connection,
+            stream_name: stream_name.to_string(),
+            codec_header: policy.codec.header(),
+            policy,
+            retention,
+            receiver,
+            spill,
+            lagging: false,
+            backpressure: backpressure.clone(),
+            level: level_tx,
+            shed: shed.clone(),
+            published: 0,
+            batches: 0,
+            peak_depth: 0,
+        };
+        let publisher = Self {
+            queue,
+            codec: policy.codec,
+            backpressure,
+            level,
+            shed,
+        };
+        Ok((publisher, tokio::spawn(task.run())))
+    }
+
+    /// Encodes `event` and queues it for the stream.
+    pub async fn publish(&self, event: &IndexEvent) -> Result<()> {
+        let payload = self.codec.encode(event)?;
+        self.send(Publish::Event(payload)).await
+    }
+
+    /// Queues an update of the current slot key.
+    pub async fn set_current_slot(&self, slot: u64) -> Result<()> {
+        self.send(Publish::CurrentSlot(slot)).await
+    }
+
+    /// Whether backpressure drops `event` instead of publishing it; counts it if so.
+    pub fn sheds(&self, event: &IndexEvent) -> bool {
+        let shed = self.backpressure.sheds(*self.level.borrow(), event);
+        if shed {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+        }
+        shed
+    }
+
+    /// Follows the backpressure level, updated after every lag check.
+    pub fn backpressure(&self) -> watch::Receiver<BackpressureLevel> {
+        self.level.clone()
+    }
+
+    /// Number of writes waiting for the publisher task.
+    pub fn queue_depth(&self) -> usize {
+        self.queue.max_capacity() - self.queue.capacity()
+    }
+
+    async fn send(&self, publish: Publish) -> Result<()> {
+        self.queue
+            .send(publish)
+            .await
+            .map_err(|_| anyhow!("Redis publisher stopped"))
+    }
+}
+
+struct PublisherTask {
+    connection: MultiplexedConnection,
+    stream_name: String,
+    /// Written to every entry's codec field.
+    codec_header: String,
+    policy: PublishPolicy,
+    retention: RetentionPolicy,
+    receiver: mpsc::Receiver<Publish>,
+    spill: Option<SpillFile>,
+    /// A consumer group lags past the trim point, as of the last check.
+    lagging: bool,
+    backpressure: Arc<BackpressurePolicy>,
+    level: watch::Sender<BackpressureLevel>,
+    shed: Arc<AtomicU64>,
+    published: u64,
+    batches: u64,
+    peak_depth: usize,
+}
+
+impl PublisherTask {
+    async fn run(mut self) -> Result<()> {
+        let mut batch = Vec::with_capacity(self.policy.max_batch);
+        let mut metrics = tokio::time::interval(self.policy.metrics_interval);
+        let mut lag_check = tokio::time::interval(self.retention.check_interval);
+
+        loop {
+            let paused = self.lagging && self.retention.lag_policy == LagPolicy::Pause;
+            tokio::select! {
+                received = self.receiver.recv_many(&mut batch, self.policy.max_batch), if !paused => {
+                    if received == 0 {
+                        info!("Redis publisher stopped after {} writes", self.published);
+                        return Ok(());
+                    }
+                    // What is still queued after taking this batch.
+                    let depth = self.receiver.len();
+                    self.peak_depth = self.peak_depth.max(depth + received);
+                    self.flush(&mut batch).await?;
+                }
+                _ = metrics.tick() => self.log_metrics(),
+                _ = lag_check.tick() => self.check_lag().await?,
+            }
+        }
+    }
+
+    /// Sends the batch as one pipeline, preserving queue order. While spilling, events
+    /// go to the spill file and only the current slot is written to Redis.
+    async fn flush(&mut self, batch: &mut Vec<Publish>) -> Result<()> {
+        let spill = match self.spill.as_mut() {
+            // Keep spilling until the file is replayed, so events stay in order.
+            Some(spill) if self.lagging || !spill.is_empty() => Some(spill),
+            _ => None,
+        };
+
+        let options = self.retention.trim.add_options();
+        let mut pipe = redis::pipe();
+        let mut writes = 0;
+        let mut spilled = Vec::new();
+        for publish in batch.iter() {
+            match publish {
+                Publish::Event(payload) if spill.is_some() => spilled.push(payload.as_slice()),
+                Publish::Event(payload) => {
+                    let fields: [(&str, &[u8]); 2] = [
+                        (PAYLOAD_FIELD, payload),
+                        (CODEC_FIELD, self.codec_header.as_bytes()),
+                    ];
+                    pipe.xadd_options(&self.stream_name, "*", &fields, &options)
+                        .ignore();
+                    writes += 1;
+                }
+                Publish::CurrentSlot(slot) => {
+                    pipe.set(CURRENT_SLOT_KEY, slot).ignore();
+                    writes += 1;
+                }
+            }
+        }
+        if let Some(spill) = spill {
+            spill.append(&self.codec_header, &spilled).await?;
+        }
+
+        if writes > 0 {
+            pipe.query_async::<()>(&mut self.connection)
+                .await
+                .with_context(|| format!("Failed to publish {} writes to Redis", writes))?;
+        }
+
+        self.published += batch.len() as u64;
+        self.batches += 1;
+        batch.clear();
+        Ok(())
+    }
+
+    /// Refreshes the lag state from the stream's consumer groups. Once no group lags,
+    /// spilled events are replayed before anything newer is published.
+    async fn check_lag(&mut self) -> Result<()> {
+        let status = StreamStatus::fetch(&mut self.connection, &self.stream_name).await?;
+        let lagging: Vec<String> = status
+            .lagging(&self.retention.trim)
+            .map(|group| group.name.clone())
+            .collect();
+
+        if !lagging.is_empty() {
+            warn!(
+                "Consumer groups [{}] lag past the trim point of {} ({} entries); lag policy {:?}",
+                lagging.join(", "),
+                self.stream_name,
+                status.length,
+                self.retention.lag_policy
+            );
+        } else if self.lagging {
+            info!("Consumer groups of {} caught up", self.stream_name);
+        }
+        self.lagging = !lagging.is_empty();
+
+        if self.backpressure.is_enabled() {
+            let current = *self.level.borrow();
+            let level = self.backpressure.level(&status, current);
+            if level != current {
+                warn!(
+                    "Backpressure {:?} -> {:?} (stream {} has {} entries)",
+                    current, level, self.stream_name, status.length
+                );
+                self.level.send_replace(level);
+            }
+        }
+
+        if let Some(spill) = self.spill.as_mut() {
+            if !self.lagging && !spill.is_empty() {
+                // Replayed entries are not trimmed, so replay only as many as keep every
+                // group short of the trim point; the next check continues.
+                let step = self
+                    .retention
+                    .trim
+                    .headroom(&status)
+                    .unwrap_or(usize::MAX)
+                    .min(self.policy.max_batch * REPLAY_STEP_BATCHES);
+                if step > 0 {
+                    let replayed = spill
+                        .replay(
+                            &mut self.connection,
+                            &self.stream_name,
+                            step,
+                            self.policy.max_batch,
+                        )
+                        .await?;
+                    info!(
+                        "Replayed {} spilled events into {}{}",
+                        replayed,
+                        self.stream_name,
+                        if spill.is_empty() { "" } else { ", more to go" }
+                    );
+                    self.published += replayed;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn log_metrics(&mut self) {
+        info!(
+            "Redis publisher: queue depth {}/{} (peak {}), {} writes in {} pipelines, {} events shed, backpressure {:?}",
+            self.receiver.len(),
+            self.policy.queue_capacity,
+            self.peak_depth,
+            self.published,
+            self.batches,
+            self.shed.load(Ordering::Relaxed),
+            *self.level.borrow()
+        );
+        self.peak_depth = 0;
+    }
+}
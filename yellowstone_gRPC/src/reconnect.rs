@@ -0,0 +1,164 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// Settings for the supervised reconnect loop around the Geyser stream.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Give up after this many consecutive failed attempts. `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_attempts: None,
+        }
+    }
+}
+
+/// Exponential backoff with full jitter.
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self { policy, attempt: 0 }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Returns the delay before the next attempt, or `None` once `max_attempts` is exhausted.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempt >= max_attempts {
+                return None;
+            }
+        }
+
+        // The exponent outgrows `Duration` after a few dozen attempts when retrying
+        // forever, so anything past it is clamped to `max_backoff` instead of panicking.
+        let exponent = self.policy.multiplier.powi(self.attempt as i32);
+        let ceiling =
+            Duration::try_from_secs_f64(self.policy.initial_backoff.as_secs_f64() * exponent)
+                .unwrap_or(self.policy.max_backoff)
+                .min(self.policy.max_backoff);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let jittered_ms = rand::rng().random_range(0..=ceiling.as_millis() as u64);
+        Some(Duration::from_millis(jittered_ms))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Tracks which slots have been completely pushed to Redis so a new subscription
+/// can resume with `SubscribeRequest.from_slot` instead of starting at the tip. Events
+/// handed to the publisher count as pushed: its queue outlives gRPC reconnects.
+#[derive(Debug, Default, Clone)]
+pub struct SlotCursor {
+    current_slot: Option<u64>,
+    last_complete_slot: Option<u64>,
+}
+
+impl SlotCursor {
+    /// Records that an event for `slot` was pushed. Seeing a newer slot means every
+    /// event of the previous one has already been pushed.
+    pub fn observe(&mut self, slot: u64) {
+        match self.current_slot {
+            Some(current) if slot > current => {
+                self.last_complete_slot = Some(current);
+                self.current_slot = Some(slot);
+            }
+            None => self.current_slot = Some(slot),
+            _ => {}
+        }
+    }
+
+    pub fn last_complete_slot(&self) -> Option<u64> {
+        self.last_complete_slot
+    }
+
+    /// The slot to pass as `from_slot` on resubscribe. The partially pushed slot is
+    /// replayed in full; writes downstream are keyed so duplicates are harmless.
+    pub fn resume_slot(&self) -> Option<u64> {
+        self.last_complete_slot.map(|slot| slot + 1)
+    }
+
+    /// Forgets the resume point, e.g. when the provider no longer retains that slot.
+    pub fn reset(&mut self) {
+        self.current_slot = None;
+        self.last_complete_slot = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_attempts: Option<u32>) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1_000),
+            multiplier: 2.0,
+            max_attempts,
+        }
+    }
+
+    #[test]
+    fn jitters_below_the_capped_ceiling() {
+        let mut backoff = Backoff::new(policy(None));
+        for ceiling_ms in [100, 200, 400, 800, 1_000, 1_000, 1_000] {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay <= Duration::from_millis(ceiling_ms), "{:?}", delay);
+        }
+        assert_eq!(backoff.attempt(), 7);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts_until_reset() {
+        let mut backoff = Backoff::new(policy(Some(2)));
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert!(backoff.next_delay().is_some());
+
+        let mut forever = Backoff::new(policy(None));
+        assert!((0..1_000).all(|_| forever.next_delay().is_some()));
+    }
+
+    #[test]
+    fn resumes_from_the_partially_pushed_slot() {
+        let mut cursor = SlotCursor::default();
+        cursor.observe(10);
+        assert_eq!(cursor.resume_slot(), None);
+
+        cursor.observe(10);
+        cursor.observe(12);
+        assert_eq!(cursor.last_complete_slot(), Some(10));
+        assert_eq!(cursor.resume_slot(), Some(11));
+
+        // A late event for an older slot does not move the cursor back.
+        cursor.observe(11);
+        assert_eq!(cursor.last_complete_slot(), Some(10));
+
+        cursor.reset();
+        assert_eq!(cursor.resume_slot(), None);
+        cursor.observe(20);
+        assert_eq!(cursor.last_complete_slot(), None);
+    }
+}
@@ -0,0 +1,573 @@
+use crate::codec::{CODEC_FIELD, PAYLOAD_FIELD};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{
+    StreamAddOptions, StreamInfoGroupsReply, StreamPendingReply, StreamRangeReply,
+    StreamTrimStrategy, StreamTrimmingMode,
+};
+use redis::AsyncCommands;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter};
+use tracing::{info, warn};
+
+/// A group counts as lagging past the trim point once the entries it still needs reach
+/// this share of the trim bound, before anything it needs is actually trimmed.
+pub const LAG_ALERT_RATIO: f64 = 0.8;
+
+/// How the stream is trimmed on every XADD. Trimming is approximate (`~`), which lets
+/// Redis drop whole macro nodes and keeps it cheap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamTrim {
+    /// Never trim.
+    #[default]
+    None,
+    /// Keep about this many entries (`MAXLEN ~`).
+    MaxLen(usize),
+    /// Drop entries older than this (`MINID ~` at the corresponding timestamp).
+    MaxAge(Duration),
+}
+
+impl StreamTrim {
+    /// XADD options for a write happening now.
+    pub fn add_options(&self) -> StreamAddOptions {
+        match self {
+            Self::None => StreamAddOptions::default(),
+            Self::MaxLen(max_len) => StreamAddOptions::default().trim(StreamTrimStrategy::maxlen(
+                StreamTrimmingMode::Approx,
+                *max_len,
+            )),
+            Self::MaxAge(max_age) => {
+                let min_ms = now_ms().saturating_sub(max_age.as_millis() as u64);
+                StreamAddOptions::default().trim(StreamTrimStrategy::minid(
+                    StreamTrimmingMode::Approx,
+                    format!("{}-0", min_ms),
+                ))
+            }
+        }
+    }
+
+    /// Whether `group` still needs entries close enough to the trim point that they are
+    /// about to be dropped. Unknown lag (servers before Redis 7) never counts.
+    pub fn is_lagging(&self, group: &GroupLag) -> bool {
+        match self {
+            Self::None => false,
+            Self::MaxLen(max_len) => group.lag.is_some_and(|lag| {
+                (lag + group.pending) as f64 >= *max_len as f64 * LAG_ALERT_RATIO
+            }),
+            Self::MaxAge(max_age) => group.oldest_needed_ms.is_some_and(|oldest_ms| {
+                let age_ms = now_ms().saturating_sub(oldest_ms);
+                age_ms as f64 >= max_age.as_millis() as f64 * LAG_ALERT_RATIO
+            }),
+        }
+    }
+
+    /// How many entries can be added before a group of `status` lags past the trim
+    /// point; `None` when added entries do not bring any group closer to it. A group of
+    /// unknown lag is assumed to still need the whole stream.
+    pub fn headroom(&self, status: &StreamStatus) -> Option<usize> {
+        match self {
+            Self::None | Self::MaxAge(_) => None,
+            Self::MaxLen(max_len) => {
+                let needed = status
+                    .groups
+                    .iter()
+                    .map(|group| group.lag.map_or(status.length, |lag| lag + group.pending))
+                    .max()
+                    .unwrap_or(0);
+                let limit = (*max_len as f64 * LAG_ALERT_RATIO) as usize;
+                Some(limit.saturating_sub(needed))
+            }
+        }
+    }
+}
+
+/// What the engine does while a consumer group lags past the trim point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LagPolicy {
+    /// Keep publishing and log a warning on every check.
+    #[default]
+    Alert,
+    /// Stop publishing until the group catches up; the publisher queue fills and update
+    /// decoding waits on it.
+    Pause,
+    /// Write events to the spill file instead of the stream and replay them once the
+    /// group catches up.
+    Spill,
+}
+
+impl FromStr for LagPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "alert" => Ok(Self::Alert),
+            "pause" => Ok(Self::Pause),
+            "spill" => Ok(Self::Spill),
+            other => Err(anyhow!(
+                "unknown lag policy '{}' (expected alert, pause or spill)",
+                other
+            )),
+        }
+    }
+}
+
+/// Stream trimming, what to do when consumers fall behind it and how often to check.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub trim: StreamTrim,
+    pub lag_policy: LagPolicy,
+    pub spill_path: PathBuf,
+    pub check_interval: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            trim: StreamTrim::None,
+            lag_policy: LagPolicy::Alert,
+            spill_path: PathBuf::from("spill/yellowstone_gRPC_streams.bin"),
+            check_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How far one consumer group is behind the head of the stream.
+#[derive(Debug, Clone)]
+pub struct GroupLag {
+    pub name: String,
+    /// Entries not yet delivered to the group; `None` when Redis cannot tell.
+    pub lag: Option<usize>,
+    /// Entries delivered but not acknowledged.
+    pub pending: usize,
+    /// Timestamp of the oldest entry the group still needs, pending or undelivered.
+    pub oldest_needed_ms: Option<u64>,
+}
+
+/// Length of the stream and the lag of every consumer group reading it.
+#[derive(Debug, Clone)]
+pub struct StreamStatus {
+    pub length: usize,
+    pub groups: Vec<GroupLag>,
+}
+
+impl StreamStatus {
+    pub async fn fetch(connection: &mut MultiplexedConnection, stream_name: &str) -> Result<Self> {
+        let length: usize = connection.xlen(stream_name).await?;
+        if length == 0 {
+            // XINFO GROUPS fails on a stream that does not exist yet.
+            let exists: bool = connection.exists(stream_name).await?;
+            if !exists {
+                return Ok(Self {
+                    length,
+                    groups: Vec::new(),
+                });
+            }
+        }
+
+        let reply: StreamInfoGroupsReply = connection.xinfo_groups(stream_name).await?;
+        let first_entry: StreamRangeReply =
+            connection.xrange_count(stream_name, "-", "+", 1).await?;
+        let first_entry_ms = first_entry.ids.first().and_then(|entry| id_ms(&entry.id));
+        let mut groups = Vec::with_capacity(reply.groups.len());
+        for group in reply.groups {
+            let first_pending_ms = if group.pending > 0 {
+                match connection.xpending(stream_name, &group.name).await? {
+                    StreamPendingReply::Data(data) => id_ms(&data.start_id),
+                    StreamPendingReply::Empty => None,
+                }
+            } else {
+                None
+            };
+            // The first undelivered entry comes right after the last delivered one.
+            let first_undelivered_ms = match group.lag {
+                Some(0) => None,
+                _ => id_ms(&group.last_delivered_id),
+            };
+            let oldest_needed_ms = match (first_pending_ms, first_undelivered_ms) {
+                (Some(pending), Some(undelivered)) => Some(pending.min(undelivered)),
+                (pending, undelivered) => pending.or(undelivered),
+            }
+            // Nothing older than the stream's first entry is still around to be needed
+            // (a group that never read has last delivered ID 0-0).
+            .map(|oldest_ms| first_entry_ms.map_or(oldest_ms, |first_ms| oldest_ms.max(first_ms)));
+
+            groups.push(GroupLag {
+                name: group.name,
+                lag: group.lag,
+                pending: group.pending,
+                oldest_needed_ms,
+            });
+        }
+
+        Ok(Self { length, groups })
+    }
+
+    /// Groups that lag past the trim point of `trim`.
+    pub fn lagging<'a>(&'a self, trim: &'a StreamTrim) -> impl Iterator<Item = &'a GroupLag> {
+        self.groups.iter().filter(|group| trim.is_lagging(group))
+    }
+
+    /// Logs the stream length and every group's lag, warning about lagging groups.
+    pub fn log(&self, stream_name: &str, trim: &StreamTrim) {
+        info!(
+            "Stream {}: {} entries, trim {:?}, {} consumer groups",
+            stream_name,
+            self.length,
+            trim,
+            self.groups.len()
+        );
+        for group in &self.groups {
+            let lag = group
+                .lag
+                .map_or_else(|| "unknown".to_string(), |lag| lag.to_string());
+            if trim.is_lagging(group) {
+                warn!(
+                    "Group {} lags past the trim point: {} undelivered, {} pending",
+                    group.name, lag, group.pending
+                );
+            } else {
+                info!(
+                    "Group {}: {} undelivered, {} pending",
+                    group.name, lag, group.pending
+                );
+            }
+        }
+    }
+}
+
+/// Append-only file of stream payloads written while consumers lag, replayed into the
+/// stream in order once they catch up. Each record is the length-prefixed codec header
+/// followed by the length-prefixed payload. The position of the next record to replay
+/// is saved next to the file after every pipeline, so a failed or interrupted replay
+/// resumes there and publishes at most one pipeline again.
+/// A record torn by a crash in the middle of an append is dropped.
+pub struct SpillFile {
+    path: PathBuf,
+    offset_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    /// Bytes of the file already replayed.
+    offset: u64,
+    has_records: bool,
+}
+
+impl SpillFile {
+    /// Opens the spill file at `path`, picking up records left by a previous run.
+    pub async fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut existing = match fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+        let offset_path = offset_path(path);
+        let offset = match fs::read_to_string(&offset_path).await {
+            Ok(offset) => offset
+                .trim()
+                .parse::<u64>()
+                .with_context(|| format!("Invalid replay offset in {}", offset_path.display()))?
+                .min(existing),
+            Err(_) => 0,
+        };
+        if existing > offset {
+            // A crash in the middle of an append leaves a torn record at the end. Cut it
+            // off, so records appended from now on follow the last complete one.
+            let complete = complete_length(path, offset, existing).await?;
+            if complete < existing {
+                warn!(
+                    "Dropping a torn record of {} bytes at offset {} of {}",
+                    existing - complete,
+                    complete,
+                    path.display()
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .await?
+                    .set_len(complete)
+                    .await
+                    .with_context(|| format!("Failed to truncate {}", path.display()))?;
+                existing = complete;
+            }
+        }
+        if existing > offset {
+            info!(
+                "Spill file {} holds {} bytes from a previous run ({} already replayed)",
+                path.display(),
+                existing,
+                offset
+            );
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            offset_path,
+            writer: None,
+            offset,
+            has_records: existing > offset,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.has_records
+    }
+
+    /// Appends payloads encoded with the codec named by `codec_header`.
+    pub async fn append(&mut self, codec_header: &str, payloads: &[&[u8]]) -> Result<()> {
+        if self.writer.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await
+                .with_context(|| format!("Failed to open {}", self.path.display()))?;
+            self.writer = Some(BufWriter::new(file));
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        for payload in payloads {
+            writer.write_u32_le(codec_header.len() as u32).await?;
+            writer.write_all(codec_header.as_bytes()).await?;
+            writer.write_u32_le(payload.len() as u32).await?;
+            writer.write_all(payload).await?;
+        }
+        writer.flush().await?;
+        self.has_records |= !payloads.is_empty();
+        Ok(())
+    }
+
+    /// Publishes up to `max_records` spilled payloads to the stream in order,
+    /// `max_batch` per pipeline, and empties the file once everything was replayed.
+    /// Returns how many payloads were replayed.
+    ///
+    /// Replayed entries are added without trimming: trimming them right away could drop
+    /// them before the lagging group read them. The caller bounds `max_records` by the
+    /// trim headroom instead.
+    pub async fn replay(
+        &mut self,
+        connection: &mut MultiplexedConnection,
+        stream_name: &str,
+        max_records: usize,
+        max_batch: usize,
+    ) -> Result<u64> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush().await?;
+        }
+
+        let mut file = File::open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        let length = file.metadata().await?.len();
+        file.seek(SeekFrom::Start(self.offset)).await?;
+        let mut reader = BufReader::new(file);
+        let mut offset = self.offset;
+        let mut replayed = 0;
+        let mut batch = Vec::with_capacity(max_batch);
+        let mut exhausted = false;
+        while (replayed as usize) + batch.len() < max_records {
+            let Some((header, payload)) = read_record(&mut reader).await? else {
+                if offset < length {
+                    warn!(
+                        "Dropping a torn record of {} bytes at offset {} of {}",
+                        length - offset,
+                        offset,
+                        self.path.display()
+                    );
+                }
+                exhausted = true;
+                break;
+            };
+            offset += (8 + header.len() + payload.len()) as u64;
+            batch.push((header, payload));
+
+            if batch.len() >= max_batch {
+                replayed += publish_batch(connection, stream_name, &mut batch).await?;
+                self.save_offset(offset).await?;
+            }
+        }
+        replayed += publish_batch(connection, stream_name, &mut batch).await?;
+        self.save_offset(offset).await?;
+
+        if exhausted {
+            File::create(&self.path)
+                .await
+                .with_context(|| format!("Failed to truncate {}", self.path.display()))?;
+            self.save_offset(0).await?;
+            self.has_records = false;
+        }
+        Ok(replayed)
+    }
+
+    async fn save_offset(&mut self, offset: u64) -> Result<()> {
+        if offset != self.offset {
+            fs::write(&self.offset_path, offset.to_string())
+                .await
+                .with_context(|| format!("Failed to write {}", self.offset_path.display()))?;
+            self.offset = offset;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the next spill record, or `None` at the end of the file. A record cut short by
+/// a crash in the middle of an append counts as the end of the file too.
+async fn read_record(reader: &mut (impl AsyncRead + Unpin)) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let record = async {
+        let header_length = reader.read_u32_le().await? as usize;
+        let mut header = vec![0; header_length];
+        reader.read_exact(&mut header).await?;
+        let length = reader.read_u32_le().await? as usize;
+        let mut payload = vec![0; length];
+        reader.read_exact(&mut payload).await?;
+        Ok::<_, io::Error>((header, payload))
+    };
+    match record.await {
+        Ok(record) => Ok(Some(record)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Where the last complete record of the `length` bytes long spill file at `path` ends,
+/// scanning from `offset`. Only the length prefixes are read.
+async fn complete_length(path: &Path, offset: u64, length: u64) -> Result<u64> {
+    let mut reader = BufReader::new(
+        File::open(path)
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    reader.seek(SeekFrom::Start(offset)).await?;
+    let mut end = offset;
+    loop {
+        let header_length = match reader.read_u32_le().await {
+            Ok(header_length) => u64::from(header_length),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(end),
+            Err(e) => return Err(e.into()),
+        };
+        if end + 8 + header_length > length {
+            return Ok(end);
+        }
+        reader.seek(SeekFrom::Current(header_length as i64)).await?;
+        let record_length = 8 + header_length + u64::from(reader.read_u32_le().await?);
+        if end + record_length > length {
+            return Ok(end);
+        }
+        reader.seek(SeekFrom::Start(end + record_length)).await?;
+        end += record_length;
+    }
+}
+
+/// Where the replay offset of the spill file at `path` is kept.
+fn offset_path(path: &Path) -> PathBuf {
+    let mut offset_path = path.as_os_str().to_owned();
+    offset_path.push(".offset");
+    PathBuf::from(offset_path)
+}
+
+async fn publish_batch(
+    connection: &mut MultiplexedConnection,
+    stream_name: &str,
+    batch: &mut Vec<(Vec<u8>, Vec<u8>)>,
+) -> Result<u64> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+    let mut pipe = redis::pipe();
+    for (header, payload) in batch.iter() {
+        let fields: [(&str, &[u8]); 2] = [(PAYLOAD_FIELD, payload), (CODEC_FIELD, header)];
+        pipe.xadd(stream_name, "*", &fields).ignore();
+    }
+    pipe.query_async::<()>(connection).await?;
+
+    let published = batch.len() as u64;
+    batch.clear();
+    Ok(published)
+}
+
+/// Millisecond part of a stream entry ID (`<ms>-<seq>`).
+fn id_ms(id: &str) -> Option<u64> {
+    id.split('-').next()?.parse().ok()
+}
+
+fn now_ms() -> u64 {
+    Utc::now().timestamp_millis().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(header: &str, payload: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        record.extend_from_slice(header.as_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(payload);
+        record
+    }
+
+    fn spill_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("spill-{}-{}", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_partial_trailing_record() {
+        let mut bytes = record("json", b"first");
+        let torn = record("json", b"second");
+        for cut in 1..torn.len() {
+            let mut file = bytes.clone();
+            file.extend_from_slice(&torn[..cut]);
+            let mut reader = io::Cursor::new(file);
+
+            let first = read_record(&mut reader).await.unwrap();
+            assert_eq!(first, Some((b"json".to_vec(), b"first".to_vec())));
+            assert_eq!(
+                read_record(&mut reader).await.unwrap(),
+                None,
+                "cut at {}",
+                cut
+            );
+        }
+
+        bytes.extend_from_slice(&torn);
+        let mut reader = io::Cursor::new(bytes);
+        read_record(&mut reader).await.unwrap();
+        let second = read_record(&mut reader).await.unwrap();
+        assert_eq!(second, Some((b"json".to_vec(), b"second".to_vec())));
+    }
+
+    #[tokio::test]
+    async fn drops_a_torn_record_on_open() {
+        let path = spill_path("torn");
+        let first = record("json", b"first");
+        let mut bytes = first.clone();
+        bytes.extend_from_slice(&record("json", b"second")[..7]);
+        fs::write(&path, &bytes).await.unwrap();
+
+        let mut spill = SpillFile::open(&path).await.unwrap();
+        assert!(!spill.is_empty());
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), first.len() as u64);
+
+        spill.append("json", &[b"third"]).await.unwrap();
+        let mut reader = BufReader::new(File::open(&path).await.unwrap());
+        let mut payloads = Vec::new();
+        while let Some((_, payload)) = read_record(&mut reader).await.unwrap() {
+            payloads.push(payload);
+        }
+        assert_eq!(payloads, vec![b"first".to_vec(), b"third".to_vec()]);
+
+        fs::remove_file(&path).await.unwrap();
+    }
+}
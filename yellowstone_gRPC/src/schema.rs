@@ -0,0 +1,268 @@
+use crate::types::IndexEvent;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version of the event schema this build writes. Bump it on every change to the event
+/// types, added fields included, and add the upgrade from the previous version to
+/// `UPGRADES` (a no-op for fields with a `#[serde(default)]`). Bincode has no field names,
+/// so the version is all that tells its readers the layout changed.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Schema of payloads written before events carried a version.
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Variant tags of [`IndexEvent`] this build can decode.
+const KNOWN_KINDS: &[&str] = &[
+    "Transaction",
+    "Account",
+    "Slot",
+    "Block",
+    "TransactionStatus",
+];
+
+/// Upgrades of an untyped event to the next schema version; entry `i` upgrades events
+/// of version `i + 1`.
+const UPGRADES: &[fn(&mut Value)] = &[upgrade_v1];
+
+/// An event as it goes on the stream: tagged with the schema version it was written in.
+#[derive(Debug, Serialize)]
+pub struct Envelope<'a> {
+    pub schema_version: u32,
+    pub event: &'a IndexEvent,
+}
+
+impl<'a> Envelope<'a> {
+    pub fn new(event: &'a IndexEvent) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            event,
+        }
+    }
+}
+
+/// Owned counterpart of [`Envelope`], with the event typed or untyped.
+#[derive(Debug, Deserialize)]
+pub struct OwnedEnvelope<E> {
+    pub schema_version: u32,
+    pub event: E,
+}
+
+/// A decoded stream payload.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Decoded {
+    Event(IndexEvent),
+    /// An event kind this build does not know, written by a newer producer.
+    Unknown {
+        kind: String,
+        schema_version: u32,
+    },
+}
+
+/// Builds an event of `schema_version` from its untyped form, upgrading it to
+/// [`SCHEMA_VERSION`] first. Events from newer versions are read as far as this build
+/// understands them: unknown fields are ignored and unknown kinds come back as
+/// [`Decoded::Unknown`].
+pub fn from_value(schema_version: u32, mut event: Value) -> Result<Decoded> {
+    let pending = (schema_version.max(LEGACY_SCHEMA_VERSION) - 1) as usize;
+    for upgrade in UPGRADES.iter().skip(pending) {
+        upgrade(&mut event);
+    }
+
+    let kind = match &event {
+        Value::Object(map) if map.len() == 1 => map.keys().next().cloned(),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("event of schema version {} has no kind", schema_version))?;
+    if !KNOWN_KINDS.contains(&kind.as_str()) {
+        return Ok(Decoded::Unknown {
+            kind,
+            schema_version,
+        });
+    }
+
+    let event = serde_json::from_value(event).with_context(|| {
+        format!(
+            "invalid {} event of schema version {}",
+            kind, schema_version
+        )
+    })?;
+    Ok(Decoded::Event(event))
+}
+
+/// Version 1 transactions and accounts may predate `ingested_at`; use the event time
+/// instead of the Unix epoch.
+fn upgrade_v1(event: &mut Value) {
+    for kind in ["Transaction", "Account"] {
+        if let Some(Value::Object(body)) = event.get_mut(kind) {
+            if !body.contains_key("ingested_at") {
+                if let Some(timestamp) = body.get("timestamp").cloned() {
+                    body.insert("ingested_at".to_string(), timestamp);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{Codec, Format};
+    use crate::types::{SlotStatus, SlotUpdate, SolanaAccount};
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    fn account() -> IndexEvent {
+        IndexEvent::Account(SolanaAccount {
+            pubkey: "pubkey".to_string(),
+            lamports: 1_000,
+            owner: "owner".to_string(),
+            executable: false,
+            rent_epoch: 7,
+            data: "AAECAw==".to_string(),
+            write_version: 3,
+            slot: 42,
+            txn_signature: None,
+            timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            ingested_at: Utc.timestamp_opt(1_700_000_001, 0).unwrap(),
+        })
+    }
+
+    fn decode(codec: Codec, payload: &[u8]) -> IndexEvent {
+        match codec.decode(payload).unwrap() {
+            Decoded::Event(event) => event,
+            unknown => panic!("expected an event, got {:?}", unknown),
+        }
+    }
+
+    #[test]
+    fn round_trips_every_codec() {
+        for format in [Format::Json, Format::Bincode, Format::MessagePack] {
+            for zstd_level in [None, Some(3)] {
+                let codec = Codec {
+                    format,
+                    zstd_level,
+                    ..Codec::default()
+                };
+                let payload = codec.encode(&account()).unwrap();
+                let reader = Codec::from_header(Some(&codec.header())).unwrap();
+                let IndexEvent::Account(decoded) = decode(reader, &payload) else {
+                    panic!("{} changed the event kind", codec);
+                };
+                assert_eq!(decoded.data, "AAECAw==");
+                assert_eq!(decoded.write_version, 3);
+                assert_eq!(decoded.ingested_at.timestamp(), 1_700_000_001);
+            }
+        }
+    }
+
+    #[test]
+    fn upgrades_legacy_payloads() {
+        // A version 1 entry: bare JSON without a codec header or `ingested_at`.
+        let mut legacy = serde_json::to_value(account()).unwrap();
+        legacy["Account"]
+            .as_object_mut()
+            .unwrap()
+            .remove("ingested_at");
+        let payload = serde_json::to_vec(&legacy).unwrap();
+
+        let IndexEvent::Account(decoded) = decode(Codec::from_header(None).unwrap(), &payload)
+        else {
+            panic!("legacy payload changed kind");
+        };
+        assert_eq!(decoded.ingested_at, decoded.timestamp);
+    }
+
+    #[test]
+    fn reads_newer_versions_tolerantly() {
+        let slot = IndexEvent::Slot(SlotUpdate {
+            slot: 42,
+            parent: Some(41),
+            status: SlotStatus::Confirmed,
+            dead_error: None,
+            timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        });
+        let mut event = serde_json::to_value(&slot).unwrap();
+        event["Slot"]["leader"] = json!("validator");
+        let newer = json!({ "schema_version": SCHEMA_VERSION + 1, "event": event });
+        let codec = Codec::from_header(Some("msgpack/2")).unwrap();
+        let payload = rmp_serde::to_vec_named(&newer).unwrap();
+        let IndexEvent::Slot(decoded) = decode(codec, &payload) else {
+            panic!("newer payload changed kind");
+        };
+        assert_eq!(decoded.parent, Some(41));
+
+        let unknown = json!({
+            "schema_version": SCHEMA_VERSION + 1,
+            "event": { "Vote": { "slot": 42 } },
+        });
+        let payload = rmp_serde::to_vec_named(&unknown).unwrap();
+        match codec.decode(&payload).unwrap() {
+            Decoded::Unknown { kind, .. } => assert_eq!(kind, "Vote"),
+            event => panic!("expected an unknown kind, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn crosses_schema_versions_in_every_codec() {
+        // An older producer left out `ingested_at`; a newer one added `rent_payer`.
+        let mut older = serde_json::to_value(account()).unwrap();
+        older["Account"]
+            .as_object_mut()
+            .unwrap()
+            .remove("ingested_at");
+        let older = json!({ "schema_version": SCHEMA_VERSION - 1, "event": older });
+        let mut newer = serde_json::to_value(account()).unwrap();
+        newer["Account"]["rent_payer"] = json!("payer");
+        let newer = json!({ "schema_version": SCHEMA_VERSION + 1, "event": newer });
+
+        for format in [Format::Json, Format::MessagePack] {
+            let codec = Codec {
+                format,
+                ..Codec::default()
+            };
+            let encode = |envelope: &Value| match format {
+                Format::Json => serde_json::to_vec(envelope).unwrap(),
+                _ => rmp_serde::to_vec_named(envelope).unwrap(),
+            };
+
+            let IndexEvent::Account(upgraded) = decode(codec, &encode(&older)) else {
+                panic!("{} changed the kind of an older event", codec);
+            };
+            assert_eq!(upgraded.ingested_at, upgraded.timestamp);
+            let IndexEvent::Account(read) = decode(codec, &encode(&newer)) else {
+                panic!("{} changed the kind of a newer event", codec);
+            };
+            assert_eq!(read.write_version, 3);
+            assert_eq!(read.ingested_at.timestamp(), 1_700_000_001);
+        }
+
+        // Bincode cannot tell where fields moved, so any other version is refused.
+        let codec = Codec {
+            format: Format::Bincode,
+            ..Codec::default()
+        };
+        let account = account();
+        for schema_version in [SCHEMA_VERSION - 1, SCHEMA_VERSION + 1] {
+            let payload = bincode::serialize(&(schema_version, &account, "payer")).unwrap();
+            let error = codec.decode(&payload).unwrap_err().to_string();
+            assert!(error.contains("schema version"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_bincode_schema_mismatch() {
+        let codec = Codec {
+            format: Format::Bincode,
+            ..Codec::default()
+        };
+        let account = account();
+        let older = Envelope {
+            schema_version: SCHEMA_VERSION - 1,
+            event: &account,
+        };
+        let payload = bincode::serialize(&older).unwrap();
+        assert!(codec.decode(&payload).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::client::{StreamEnd, YellowstoneClient};
+use crate::keepalive::{ping_request, KeepalivePolicy, PingAction, PingTracker};
+use crate::reconnect::{Backoff, ReconnectPolicy};
+use anyhow::{anyhow, Result};
+use futures::{channel::mpsc, Sink, SinkExt, Stream, StreamExt};
+use std::str::FromStr;
+use std::time::Instant;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc as tokio_mpsc, watch};
+use tokio::time::MissedTickBehavior;
+use tonic::{Code, Status};
+use tracing::{debug, error, info, warn};
+use yellowstone_grpc_proto::geyser::{subscribe_update, SubscribeRequest, SubscribeUpdate};
+
+/// A Yellowstone gRPC endpoint and its optional `x-token`.
+#[derive(Debug, Clone)]
+pub struct EndpointConfig {
+    pub url: String,
+    pub token: Option<String>,
+}
+
+/// How several configured endpoints are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamMode {
+    /// Stream from one endpoint at a time, moving to the next one when it fails.
+    #[default]
+    Failover,
+    /// Stream from every endpoint at once and keep whichever copy of an update arrives first.
+    Race,
+}
+
+impl FromStr for StreamMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "failover" => Ok(Self::Failover),
+            "race" => Ok(Self::Race),
+            other => Err(anyhow!(
+                "unknown stream mode '{}' (expected failover or race)",
+                other
+            )),
+        }
+    }
+}
+
+/// An update received by a worker, tagged with the endpoint it came from.
+pub struct SessionUpdate {
+    pub endpoint: String,
+    pub update: SubscribeUpdate,
+}
+
+/// Keeps one subscription alive against a list of endpoints, reconnecting with backoff
+/// and forwarding every data update to the coordinator. Pings are answered here so the
+/// coordinator only sees data.
+pub struct EndpointWorker {
+    endpoints: Vec<EndpointConfig>,
+    current: usize,
+    request: watch::Receiver<SubscribeRequest>,
+    resume_slot: watch::Receiver<Option<u64>>,
+    updates: tokio_mpsc::Sender<SessionUpdate>,
+    policy: ReconnectPolicy,
+    keepalive: KeepalivePolicy,
+}
+
+enum SessionError {
+    /// Connecting or subscribing failed; worth retrying.
+    Connect(anyhow::Error),
+    /// The coordinator stopped receiving updates; the worker should exit.
+    Shutdown,
+}
+
+impl EndpointWorker {
+    pub fn new(
+        endpoints: Vec<EndpointConfig>,
+        request: watch::Receiver<SubscribeRequest>,
+        resume_slot: watch::Receiver<Option<u64>>,
+        updates: tokio_mpsc::Sender<SessionUpdate>,
+        policy: ReconnectPolicy,
+        keepalive: KeepalivePolicy,
+    ) -> Self {
+        Self {
+            endpoints,
+            current: 0,
+            request,
+            resume_slot,
+            updates,
+            policy,
+            keepalive,
+        }
+    }
+
+    /// Runs until the coordinator goes away or `max_attempts` consecutive reconnects fail.
+    pub async fn run(mut self) -> Result<()> {
+        let mut backoff = Backoff::new(self.policy.clone());
+        let mut skip_from_slot = false;
+
+        loop {
+            let endpoint = self.endpoints[self.current].clone();
+            let mut received = 0u64;
+
+            match self
+                .run_session(&endpoint, skip_from_slot, &mut received)
+                .await
+            {
+                Ok(StreamEnd::Closed) => warn!("[{}] Stream closed by server", endpoint.url),
+                Ok(StreamEnd::KeepaliveTimeout(elapsed)) => error!(
+                    "[{}] No pong received for {:?}, treating connection as dead",
+                    endpoint.url, elapsed
+                ),
+                Ok(StreamEnd::Error(status)) => {
+                    error!("[{}] Stream Error: {}", endpoint.url, status);
+                    skip_from_slot = rejected_from_slot(&status);
+                    if skip_from_slot {
+                        warn!(
+                            "[{}] Provider rejected from_slot, resuming from the live tip",
+                            endpoint.url
+                        );
+                    }
+                }
+                Err(SessionError::Connect(e)) => {
+                    error!("[{}] Failed to connect to Yellowstone: {}", endpoint.url, e)
+                }
+                Err(SessionError::Shutdown) => return Ok(()),
+            }
+
+            if received > 0 {
+                backoff.reset();
+                skip_from_slot = false;
+            }
+
+            // Fail over to the next endpoint straight away; back off once every
+            // endpoint in the list has been tried.
+            self.current = (self.current + 1) % self.endpoints.len();
+            if self.current != 0 && received == 0 {
+                info!("Failing over to {}", self.endpoints[self.current].url);
+                continue;
+            }
+
+            let Some(delay) = backoff.next_delay() else {
+                return Err(anyhow!(
+                    "Giving up on Yellowstone stream after {} reconnect attempts",
+                    backoff.attempt()
+                ));
+            };
+            info!(
+                "Reconnecting to {} in {:?} (attempt {}, from_slot={:?})",
+                self.endpoints[self.current].url,
+                delay,
+                backoff.attempt(),
+                *self.resume_slot.borrow()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn run_session(
+        &mut self,
+        endpoint: &EndpointConfig,
+        skip_from_slot: bool,
+        received: &mut u64,
+    ) -> Result<StreamEnd, SessionError> {
+        let mut client =
+            YellowstoneClient::create_yellowstone_client(&endpoint.url, endpoint.token.clone())
+                .await
+                .map_err(|e| SessionError::Connect(e.into()))?;
+        let (mut subscribe_tx, subscribe_rx) = YellowstoneClient::subscribe(&mut client)
+            .await
+            .map_err(|e| SessionError::Connect(e.into()))?;
+
+        let mut request = self.request.borrow_and_update().clone();
+        if !skip_from_slot {
+            request.from_slot = *self.resume_slot.borrow();
+        }
+        subscribe_tx
+            .send(request)
+            .await
+            .map_err(|e| SessionError::Connect(e.into()))?;
+
+        info!(
+            "[{}] Subscribed. Starting stream processing...",
+            endpoint.url
+        );
+        self.handle_stream(&endpoint.url, subscribe_rx, &mut subscribe_tx, received)
+            .await
+    }
+
+    async fn handle_stream(
+        &mut self,
+        endpoint: &str,
+        stream: impl Stream<Item = Result<SubscribeUpdate, Status>>,
+        sink: &mut (impl Sink<SubscribeRequest, Error = mpsc::SendError> + Unpin),
+        received: &mut u64,
+    ) -> Result<StreamEnd, SessionError> {
+        let mut stream = std::pin::pin!(stream);
+        let mut pings = PingTracker::new(self.keepalive);
+        let mut ping_interval = tokio::time::interval(pings.interval());
+        ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // An update waiting for room in the full updates channel. The stream is not read
+        // meanwhile, so pongs cannot arrive and the keepalive deadline is held.
+        let mut blocked: Option<SessionUpdate> = None;
+
+        loop {
+            tokio::select! {
+                message = stream.next(), if blocked.is_none() => match message {
+                    Some(Ok(SubscribeUpdate {
+                        update_oneof: Some(subscribe_update::UpdateOneof::Ping(_)),
+                        ..
+                    })) => {
+                        // Answer server pings so load balancers see client traffic.
+                        if sink.send(ping_request(0)).await.is_err() {
+                            return Ok(StreamEnd::Closed);
+                        }
+                    }
+                    Some(Ok(SubscribeUpdate {
+                        update_oneof: Some(subscribe_update::UpdateOneof::Pong(pong)),
+                        ..
+                    })) => {
+                        if let Some(rtt) = pings.on_pong(pong.id, Instant::now()) {
+                            debug!("[{}] Pong id={} rtt={:?}", endpoint, pong.id, rtt);
+                        }
+                    }
+                    Some(Ok(update)) => {
+                        *received += 1;
+                        let update = SessionUpdate {
+                            endpoint: endpoint.to_string(),
+                            update,
+                        };
+                        match self.updates.try_send(update) {
+                            Ok(()) => {}
+                            Err(TrySendError::Full(update)) => blocked = Some(update),
+                            Err(TrySendError::Closed(_)) => return Err(SessionError::Shutdown),
+                        }
+                    }
+                    Some(Err(error)) => return Ok(StreamEnd::Error(error)),
+                    None => return Ok(StreamEnd::Closed),
+                },
+                permit = self.updates.reserve(), if blocked.is_some() => {
+                    let Ok(permit) = permit else {
+                        return Err(SessionError::Shutdown);
+                    };
+                    if let Some(update) = blocked.take() {
+                        permit.send(update);
+                    }
+                }
+                changed = self.request.changed() => {
+                    if changed.is_err() {
+                        return Err(SessionError::Shutdown);
+                    }
+                    let request = self.request.borrow_and_update().clone();
+                    if sink.send(request).await.is_err() {
+                        return Ok(StreamEnd::Closed);
+                    }
+                    info!("[{}] Pushed updated subscription", endpoint);
+                }
+                _ = ping_interval.tick() => {
+                    if blocked.is_some() {
+                        pings.restart_deadline(Instant::now());
+                        continue;
+                    }
+                    match pings.tick(Instant::now()) {
+                        Some(PingAction::Send(id)) => {
+                            let sent = sink.send(ping_request(id)).await;
+                            if sent.is_err() {
+                                return Ok(StreamEnd::Closed);
+                            }
+                        }
+                        Some(PingAction::TimedOut { elapsed, .. }) => {
+                            return Ok(StreamEnd::KeepaliveTimeout(elapsed));
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn rejected_from_slot(status: &Status) -> bool {
+    status.code() == Code::InvalidArgument && status.message().contains("from_slot")
+}
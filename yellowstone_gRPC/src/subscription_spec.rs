@@ -0,0 +1,535 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use yellowstone_grpc_proto::geyser::{
+    subscribe_request_filter_accounts_filter::Filter,
+    subscribe_request_filter_accounts_filter_lamports::Cmp,
+    subscribe_request_filter_accounts_filter_memcmp::Data, CommitmentLevel, SubscribeRequest,
+    SubscribeRequestAccountsDataSlice, SubscribeRequestFilterAccounts,
+    SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterLamports,
+    SubscribeRequestFilterAccountsFilterMemcmp, SubscribeRequestFilterBlocks,
+    SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterEntry, SubscribeRequestFilterSlots,
+    SubscribeRequestFilterTransactions,
+};
+
+/// Declarative description of a Geyser subscription, usually loaded from a TOML file.
+///
+/// Every map is keyed by the filter name Yellowstone echoes back in `SubscribeUpdate.filters`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubscriptionSpec {
+    #[serde(default)]
+    pub commitment: Option<CommitmentSpec>,
+    #[serde(default)]
+    pub accounts: HashMap<String, AccountFilterSpec>,
+    #[serde(default)]
+    pub transactions: HashMap<String, TransactionFilterSpec>,
+    #[serde(default)]
+    pub slots: HashMap<String, SlotFilterSpec>,
+    #[serde(default)]
+    pub blocks: HashMap<String, BlockFilterSpec>,
+    #[serde(default)]
+    pub blocks_meta: HashMap<String, EmptyFilterSpec>,
+    #[serde(default)]
+    pub entry: HashMap<String, EmptyFilterSpec>,
+    #[serde(default)]
+    pub accounts_data_slice: Vec<DataSliceSpec>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitmentSpec {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<CommitmentSpec> for CommitmentLevel {
+    fn from(commitment: CommitmentSpec) -> Self {
+        match commitment {
+            CommitmentSpec::Processed => CommitmentLevel::Processed,
+            CommitmentSpec::Confirmed => CommitmentLevel::Confirmed,
+            CommitmentSpec::Finalized => CommitmentLevel::Finalized,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountFilterSpec {
+    #[serde(default)]
+    pub account: Vec<String>,
+    #[serde(default)]
+    pub owner: Vec<String>,
+    #[serde(default)]
+    pub filters: Vec<AccountDataFilterSpec>,
+    #[serde(default)]
+    pub nonempty_txn_signature: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum AccountDataFilterSpec {
+    Memcmp(MemcmpSpec),
+    Datasize(u64),
+    TokenAccountState(bool),
+    Lamports(LamportsSpec),
+}
+
+/// Exactly one of `base58`, `base64` or `bytes` must be set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemcmpSpec {
+    pub offset: u64,
+    #[serde(default)]
+    pub base58: Option<String>,
+    #[serde(default)]
+    pub base64: Option<String>,
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum LamportsSpec {
+    Eq(u64),
+    Ne(u64),
+    Lt(u64),
+    Gt(u64),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionFilterSpec {
+    #[serde(default)]
+    pub vote: Option<bool>,
+    #[serde(default)]
+    pub failed: Option<bool>,
+    #[serde(default)]
+    pub signature: Option<String>,
+    #[serde(default)]
+    pub account_include: Vec<String>,
+    #[serde(default)]
+    pub account_exclude: Vec<String>,
+    #[serde(default)]
+    pub account_required: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlotFilterSpec {
+    #[serde(default)]
+    pub filter_by_commitment: Option<bool>,
+    #[serde(default)]
+    pub interslot_updates: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockFilterSpec {
+    #[serde(default)]
+    pub account_include: Vec<String>,
+    #[serde(default)]
+    pub include_transactions: Option<bool>,
+    #[serde(default)]
+    pub include_accounts: Option<bool>,
+    #[serde(default)]
+    pub include_entries: Option<bool>,
+}
+
+/// `blocks_meta` and `entry` filters carry no options; `[blocks_meta.all]` enables them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmptyFilterSpec {}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSliceSpec {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl SubscriptionSpec {
+    pub fn from_toml_str(contents: &str) -> Result<Self> {
+        let spec: Self = toml::from_str(contents).context("Failed to parse subscription spec")?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Checks the spec for mistakes Yellowstone would otherwise reject at subscribe time
+    /// (or silently match nothing for), naming the offending filter in the error.
+    pub fn validate(&self) -> Result<()> {
+        if self.accounts.is_empty()
+            && self.transactions.is_empty()
+            && self.slots.is_empty()
+            && self.blocks.is_empty()
+            && self.blocks_meta.is_empty()
+            && self.entry.is_empty()
+        {
+            bail!("Subscription spec does not declare any filters");
+        }
+
+        for (name, filter) in &self.accounts {
+            let path = format!("accounts.{}", name);
+            validate_pubkeys(&format!("{}.account", path), &filter.account)?;
+            validate_pubkeys(&format!("{}.owner", path), &filter.owner)?;
+            for (i, data_filter) in filter.filters.iter().enumerate() {
+                data_filter
+                    .to_proto()
+                    .with_context(|| format!("{}.filters[{}]", path, i))?;
+            }
+        }
+
+        for (name, filter) in &self.transactions {
+            let path = format!("transactions.{}", name);
+            validate_pubkeys(
+                &format!("{}.account_include", path),
+                &filter.account_include,
+            )?;
+            validate_pubkeys(
+                &format!("{}.account_exclude", path),
+                &filter.account_exclude,
+            )?;
+            validate_pubkeys(
+                &format!("{}.account_required", path),
+                &filter.account_required,
+            )?;
+            if let Some(signature) = &filter.signature {
+                let decoded = bs58::decode(signature)
+                    .into_vec()
+                    .map_err(|e| anyhow!("{}.signature: invalid base58: {}", path, e))?;
+                if decoded.len() != 64 {
+                    bail!(
+                        "{}.signature: expected 64 bytes, got {}",
+                        path,
+                        decoded.len()
+                    );
+                }
+            }
+        }
+
+        for (name, filter) in &self.blocks {
+            validate_pubkeys(
+                &format!("blocks.{}.account_include", name),
+                &filter.account_include,
+            )?;
+        }
+
+        for (i, slice) in self.accounts_data_slice.iter().enumerate() {
+            if slice.length == 0 {
+                bail!("accounts_data_slice[{}]: length must be greater than 0", i);
+            }
+        }
+        let mut slices = self.accounts_data_slice.clone();
+        slices.sort_by_key(|slice| slice.offset);
+        for pair in slices.windows(2) {
+            if pair[0].offset + pair[0].length > pair[1].offset {
+                bail!(
+                    "accounts_data_slice: slice at offset {} overlaps slice at offset {}",
+                    pair[0].offset,
+                    pair[1].offset
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_subscribe_request(&self) -> Result<SubscribeRequest> {
+        self.validate()?;
+
+        let mut accounts = HashMap::new();
+        for (name, filter) in &self.accounts {
+            accounts.insert(
+                name.clone(),
+                SubscribeRequestFilterAccounts {
+                    account: filter.account.clone(),
+                    owner: filter.owner.clone(),
+                    filters: filter
+                        .filters
+                        .iter()
+                        .map(AccountDataFilterSpec::to_proto)
+                        .collect::<Result<Vec<_>>>()?,
+                    nonempty_txn_signature: filter.nonempty_txn_signature,
+                },
+            );
+        }
+
+        let transactions = self
+            .transactions
+            .iter()
+            .map(|(name, filter)| {
+                (
+                    name.clone(),
+                    SubscribeRequestFilterTransactions {
+                        vote: filter.vote,
+                        failed: filter.failed,
+                        signature: filter.signature.clone(),
+                        account_include: filter.account_include.clone(),
+                        account_exclude: filter.account_exclude.clone(),
+                        account_required: filter.account_required.clone(),
+                    },
+                )
+            })
+            .collect();
+
+        let slots = self
+            .slots
+            .iter()
+            .map(|(name, filter)| {
+                (
+                    name.clone(),
+                    SubscribeRequestFilterSlots {
+                        filter_by_commitment: filter.filter_by_commitment,
+                        interslot_updates: filter.interslot_updates,
+                    },
+                )
+            })
+            .collect();
+
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|(name, filter)| {
+                (
+                    name.clone(),
+                    SubscribeRequestFilterBlocks {
+                        account_include: filter.account_include.clone(),
+                        include_transactions: filter.include_transactions,
+                        include_accounts: filter.include_accounts,
+                        include_entries: filter.include_entries,
+                    },
+                )
+            })
+            .collect();
+
+        let blocks_meta = self
+            .blocks_meta
+            .keys()
+            .map(|name| (name.clone(), SubscribeRequestFilterBlocksMeta {}))
+            .collect();
+
+        let entry = self
+            .entry
+            .keys()
+            .map(|name| (name.clone(), SubscribeRequestFilterEntry {}))
+            .collect();
+
+        Ok(SubscribeRequest {
+            accounts,
+            transactions,
+            blocks,
+            blocks_meta,
+            entry,
+            commitment: self
+                .commitment
+                .map(|commitment| CommitmentLevel::from(commitment) as i32),
+            accounts_data_slice: self
+                .accounts_data_slice
+                .iter()
+                .map(|slice| SubscribeRequestAccountsDataSlice {
+                    offset: slice.offset,
+                    length: slice.length,
+                })
+                .collect(),
+            ping: None,
+            from_slot: None,
+            slots,
+            transactions_status: HashMap::new(),
+        })
+    }
+}
+
+impl AccountDataFilterSpec {
+    fn to_proto(&self) -> Result<SubscribeRequestFilterAccountsFilter> {
+        let filter = match self {
+            AccountDataFilterSpec::Memcmp(memcmp) => {
+                let data = match (&memcmp.base58, &memcmp.base64, &memcmp.bytes) {
+                    (Some(base58), None, None) => {
+                        bs58::decode(base58)
+                            .into_vec()
+                            .map_err(|e| anyhow!("memcmp.base58: invalid base58: {}", e))?;
+                        Data::Base58(base58.clone())
+                    }
+                    (None, Some(base64), None) => {
+                        use base64::{engine::general_purpose, Engine as _};
+                        general_purpose::STANDARD
+                            .decode(base64)
+                            .map_err(|e| anyhow!("memcmp.base64: invalid base64: {}", e))?;
+                        Data::Base64(base64.clone())
+                    }
+                    (None, None, Some(bytes)) => Data::Bytes(bytes.clone()),
+                    _ => bail!("memcmp: set exactly one of base58, base64 or bytes"),
+                };
+                Filter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                    offset: memcmp.offset,
+                    data: Some(data),
+                })
+            }
+            AccountDataFilterSpec::Datasize(size) => Filter::Datasize(*size),
+            AccountDataFilterSpec::TokenAccountState(state) => Filter::TokenAccountState(*state),
+            AccountDataFilterSpec::Lamports(lamports) => {
+                let cmp = match *lamports {
+                    LamportsSpec::Eq(value) => Cmp::Eq(value),
+                    LamportsSpec::Ne(value) => Cmp::Ne(value),
+                    LamportsSpec::Lt(value) => Cmp::Lt(value),
+                    LamportsSpec::Gt(value) => Cmp::Gt(value),
+                };
+                Filter::Lamports(SubscribeRequestFilterAccountsFilterLamports { cmp: Some(cmp) })
+            }
+        };
+
+        Ok(SubscribeRequestFilterAccountsFilter {
+            filter: Some(filter),
+        })
+    }
+}
+
+fn validate_pubkeys(path: &str, pubkeys: &[String]) -> Result<()> {
+    for (i, pubkey) in pubkeys.iter().enumerate() {
+        validate_pubkey(pubkey).with_context(|| format!("{}[{}]", path, i))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_pubkey(pubkey: &str) -> Result<()> {
+    let decoded = bs58::decode(pubkey)
+        .into_vec()
+        .map_err(|e| anyhow!("invalid pubkey '{}': {}", pubkey, e))?;
+    if decoded.len() != 32 {
+        bail!(
+            "invalid pubkey '{}': expected 32 bytes, got {}",
+            pubkey,
+            decoded.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+    fn error(contents: &str) -> String {
+        format!(
+            "{:#}",
+            SubscriptionSpec::from_toml_str(contents).unwrap_err()
+        )
+    }
+
+    #[test]
+    fn accepts_a_valid_spec() {
+        let spec = SubscriptionSpec::from_toml_str(&format!(
+            r#"
+            commitment = "confirmed"
+            accounts_data_slice = [{{ offset = 0, length = 32 }}, {{ offset = 32, length = 32 }}]
+
+            [accounts.tokens]
+            owner = ["{TOKEN_PROGRAM}"]
+            filters = [{{ datasize = 165 }}, {{ memcmp = {{ offset = 32, base58 = "{TOKEN_PROGRAM}" }} }}]
+
+            [transactions.token_program]
+            vote = false
+            account_include = ["{TOKEN_PROGRAM}"]
+            "#
+        ))
+        .unwrap();
+
+        let request = spec.to_subscribe_request().unwrap();
+        assert_eq!(request.commitment, Some(CommitmentLevel::Confirmed as i32));
+        assert_eq!(request.accounts["tokens"].filters.len(), 2);
+        assert_eq!(request.accounts_data_slice.len(), 2);
+    }
+
+    #[test]
+    fn rejects_a_spec_without_filters() {
+        assert_eq!(
+            error(r#"commitment = "processed""#),
+            "Subscription spec does not declare any filters"
+        );
+    }
+
+    #[test]
+    fn names_the_filter_with_an_invalid_pubkey() {
+        let message = error(
+            r#"
+            [accounts.tokens]
+            owner = ["not-a-pubkey"]
+            "#,
+        );
+        assert!(
+            message.starts_with("accounts.tokens.owner[0]: "),
+            "{}",
+            message
+        );
+
+        let message = error(&format!(
+            r#"
+            [blocks.tokens]
+            account_include = ["{TOKEN_PROGRAM}", "{}"]
+            "#,
+            bs58::encode([1u8; 31]).into_string()
+        ));
+        assert!(
+            message.starts_with("blocks.tokens.account_include[1]: ")
+                && message.ends_with("expected 32 bytes, got 31"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn rejects_a_signature_of_the_wrong_length() {
+        let message = error(&format!(
+            r#"
+            [transactions.one]
+            signature = "{TOKEN_PROGRAM}"
+            "#
+        ));
+        assert_eq!(
+            message,
+            "transactions.one.signature: expected 64 bytes, got 32"
+        );
+    }
+
+    #[test]
+    fn rejects_an_ambiguous_memcmp() {
+        let message = error(
+            r#"
+            [accounts.tokens]
+            filters = [{ memcmp = { offset = 0, base64 = "AQID", bytes = [1, 2, 3] } }]
+            "#,
+        );
+        assert_eq!(
+            message,
+            "accounts.tokens.filters[0]: memcmp: set exactly one of base58, base64 or bytes"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_overlapping_data_slices() {
+        let message = error(
+            r#"
+            accounts_data_slice = [{ offset = 0, length = 0 }]
+            [slots.all]
+            "#,
+        );
+        assert_eq!(
+            message,
+            "accounts_data_slice[0]: length must be greater than 0"
+        );
+
+        let message = error(
+            r#"
+            accounts_data_slice = [{ offset = 16, length = 8 }, { offset = 0, length = 20 }]
+            [slots.all]
+            "#,
+        );
+        assert_eq!(
+            message,
+            "accounts_data_slice: slice at offset 0 overlaps slice at offset 16"
+        );
+    }
+}
@@ -1,10 +1,51 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 
+use anyhow::{anyhow, Result};
 use yellowstone_grpc_proto::geyser::{
     CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts,
     SubscribeRequestFilterTransactions,
 };
 
+/// What the engine subscribes to for transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionMode {
+    /// Full transactions plus the account filters.
+    #[default]
+    Full,
+    /// Only transaction statuses (signature, slot, index, error): every transaction
+    /// filter becomes a `transactions_status` filter and account filters are dropped.
+    Status,
+}
+
+impl TransactionMode {
+    /// Rewrites `request` for this mode. Safe to call again after the request changed.
+    pub fn apply(self, request: &mut SubscribeRequest) {
+        if self == Self::Full {
+            return;
+        }
+        request
+            .transactions_status
+            .extend(request.transactions.drain());
+        request.accounts.clear();
+    }
+}
+
+impl FromStr for TransactionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "status" => Ok(Self::Status),
+            other => Err(anyhow!(
+                "unknown transaction mode '{}' (expected full or status)",
+                other
+            )),
+        }
+    }
+}
+
 pub struct Subscriptions;
 
 impl Subscriptions {
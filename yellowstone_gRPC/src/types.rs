@@ -1,29 +1,150 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SolanaTransaction {
     pub signature: String,
     pub slot: u64,
     pub is_vote: bool,
     pub index: u64,
     pub success: bool,
+    /// Why the transaction failed; `None` when it succeeded.
+    #[serde(default)]
+    pub error: Option<TransactionErrorInfo>,
     pub fee: Option<u64>,
+    /// Part of `fee` paid for priority: compute unit price times the requested limit.
+    #[serde(default)]
+    pub priority_fee: Option<u64>,
+    /// `fee` minus `priority_fee`, i.e. the signature fees.
+    #[serde(default)]
+    pub base_fee: Option<u64>,
     pub compute_units_consumed: Option<u64>,
+    /// Cost model units the scheduler charged the transaction against the block limit.
+    #[serde(default)]
+    pub cost_units: Option<u64>,
+    /// Settings from the transaction's ComputeBudget program instructions.
+    #[serde(default)]
+    pub compute_budget: ComputeBudget,
     pub instructions: Vec<TransactionInstruction>,
+    /// Instructions invoked through CPI, in execution order.
+    #[serde(default)]
+    pub inner_instructions: Vec<InnerInstruction>,
+    /// Static keys followed by addresses loaded from lookup tables (writable, then
+    /// readonly).
     pub account_keys: Vec<String>,
+    /// Aligned with `account_keys`.
+    #[serde(default)]
+    pub is_writable: Vec<bool>,
+    /// Aligned with `account_keys`.
+    #[serde(default)]
+    pub is_signer: Vec<bool>,
+    /// Message version; `None` for legacy transactions.
+    #[serde(default)]
+    pub version: Option<u8>,
     pub log_messages: Vec<String>,
     pub pre_balances: Vec<u64>,
     pub post_balances: Vec<u64>,
+    #[serde(default)]
+    pub pre_token_balances: Vec<TokenBalance>,
+    #[serde(default)]
+    pub post_token_balances: Vec<TokenBalance>,
+    /// Net token movement per `(owner, mint)`, derived from the token balances.
+    #[serde(default)]
+    pub token_balance_changes: Vec<TokenBalanceChange>,
+    #[serde(default)]
+    pub rewards: Vec<Reward>,
+    #[serde(default)]
+    pub return_data: Option<ReturnData>,
+    /// Block time of `slot`, or the ingest time if the block time never arrived.
     pub timestamp: DateTime<Utc>,
+    /// When the engine received the transaction from Yellowstone.
+    #[serde(default)]
+    pub ingested_at: DateTime<Utc>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComputeBudget {
+    pub unit_limit: Option<u32>,
+    pub unit_price_micro_lamports: Option<u64>,
+    pub heap_frame_bytes: Option<u32>,
+    pub loaded_accounts_data_size_limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reward {
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: u64,
+    /// `Fee`, `Rent`, `Staking` or `Voting`.
+    pub reward_type: Option<String>,
+    pub commission: Option<u8>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReturnData {
+    pub program_id: String,
+    #[serde(with = "crate::codec::base64_bytes")]
+    pub data: String, // base64 encoded
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionErrorInfo {
+    /// `TransactionError` variant, e.g. `InstructionError` or `InsufficientFundsForFee`.
+    pub kind: String,
+    /// Index of the failing instruction for `InstructionError`.
+    pub instruction_index: Option<u8>,
+    /// `InstructionError` variant, e.g. `Custom` or `InsufficientFunds`.
+    pub instruction_error: Option<String>,
+    /// Program-specific code of an `InstructionError::Custom`, e.g. a slippage error.
+    pub custom_code: Option<u32>,
+    /// Human-readable description of the whole error.
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionInstruction {
     pub program_id: String,
     pub accounts: Vec<String>,
+    #[serde(with = "crate::codec::base64_bytes")]
     pub data: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InnerInstruction {
+    /// Index of the top-level instruction that made the CPI.
+    pub parent_index: u32,
+    /// Invocation depth; top-level instructions are at height 1. `None` on old blocks.
+    pub stack_height: Option<u32>,
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    #[serde(with = "crate::codec::base64_bytes")]
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBalance {
+    /// Index into the transaction's `account_keys`.
+    pub account_index: u32,
+    /// The token account, resolved from `account_index`.
+    pub account: String,
+    pub mint: String,
+    pub owner: String,
+    pub program_id: String,
+    /// Raw amount in base units.
+    pub amount: u64,
+    pub ui_amount: f64,
+    pub decimals: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBalanceChange {
+    pub owner: String,
+    pub mint: String,
+    pub decimals: u32,
+    /// Post minus pre balance in base units, summed over the owner's token accounts.
+    pub delta: i128,
+    pub ui_delta: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolanaAccount {
     pub pubkey: String,
@@ -31,17 +152,109 @@ pub struct SolanaAccount {
     pub owner: String,
     pub executable: bool,
     pub rent_epoch: u64,
+    #[serde(with = "crate::codec::base64_bytes")]
     pub data: String, // base64 encoded
     pub write_version: u64,
     pub slot: u64,
     pub txn_signature: Option<String>, // base58 encoded if present
+    /// Block time of `slot`, or the ingest time if the block time never arrived.
+    pub timestamp: DateTime<Utc>,
+    /// When the engine received the account update from Yellowstone.
+    #[serde(default)]
+    pub ingested_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolanaBlock {
+    pub slot: u64,
+    pub blockhash: String,
+    pub parent_slot: u64,
+    pub parent_blockhash: String,
+    pub block_height: Option<u64>,
+    pub block_time: Option<DateTime<Utc>>,
+    pub rewards: Vec<Reward>,
+    pub executed_transaction_count: u64,
+    pub entries_count: u64,
+    /// `None` when the subscription did not deliver the slot's entries.
+    pub entries: Option<Vec<BlockEntry>>,
+    /// Only known for full block updates.
+    pub updated_account_count: Option<u64>,
+    /// Block time of `slot`, or the ingest time if the block has none.
+    pub timestamp: DateTime<Utc>,
+    /// When the engine received the block from Yellowstone.
+    pub ingested_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockEntry {
+    pub index: u64,
+    pub num_hashes: u64,
+    pub hash: String, // base58 encoded
+    pub executed_transaction_count: u64,
+    pub starting_transaction_index: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotStatus {
+    Processed,
+    Confirmed,
+    Finalized,
+    Dead,
+}
+
+impl SlotStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlotStatus::Processed => "processed",
+            SlotStatus::Confirmed => "confirmed",
+            SlotStatus::Finalized => "finalized",
+            SlotStatus::Dead => "dead",
+        }
+    }
+
+    /// Order in which a slot moves through the statuses. Finalized and dead are both
+    /// final and never follow each other.
+    pub fn rank(&self) -> u8 {
+        match self {
+            SlotStatus::Processed => 1,
+            SlotStatus::Confirmed => 2,
+            SlotStatus::Finalized | SlotStatus::Dead => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotUpdate {
+    pub slot: u64,
+    pub parent: Option<u64>,
+    pub status: SlotStatus,
+    pub dead_error: Option<String>,
+    /// When the Geyser plugin emitted the update (ingest time if the server did not say).
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Outcome of a transaction without its payload, from a `transactions_status` filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionStatus {
+    pub signature: String,
+    pub slot: u64,
+    pub index: u64,
+    pub is_vote: bool,
+    pub success: bool,
+    pub error: Option<TransactionErrorInfo>,
+    /// Block time of `slot`, or the ingest time if it never became known.
     pub timestamp: DateTime<Utc>,
+    /// When the engine received the update from Yellowstone.
+    pub ingested_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum IndexEvent {
     Transaction(SolanaTransaction),
     Account(SolanaAccount),
-    Slot(u64),
-    Block(u64),
+    Slot(SlotUpdate),
+    Block(SolanaBlock),
+    TransactionStatus(TransactionStatus),
 }